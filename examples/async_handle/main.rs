@@ -0,0 +1,162 @@
+//! Sends queries and updates from async code through an `AsyncHandle`, polled here by a minimal
+//! executor in place of e.g. tokio.
+//!
+//! `cargo run --example async_handle`
+
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{App, Error, PeekResult, Responder, SysDiff, SysTime, WorkerState};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use timely::dataflow::Scope;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug)]
+enum Query {
+    // the sum of all balances
+    Total,
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = i64;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<i64>,
+    ) {
+        match query {
+            Query::Total => {
+                let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+                state.peeks.push(Box::new(move || {
+                    if !trace_beyond(&mut trace, &time) {
+                        return PeekResult::NotReady;
+                    }
+                    responder.respond(total(&mut trace, time));
+                    PeekResult::Done
+                }));
+            }
+        }
+    }
+
+    fn merge(responses: Vec<i64>) -> i64 {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+/// Run `future` on the current thread, parking it until a waker fires.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+fn main() -> Result<(), Error> {
+    let handle = BankApp.start(2);
+    let async_handle = handle.to_async();
+    let total = block_on(async {
+        let updates = (0..10)
+            .map(|id| Update::Upsert(Account { id, balance: 100 }))
+            .collect();
+        async_handle.update_batch(updates).await?;
+        async_handle.update(Update::Delete(3)).await?;
+        // async callers await their writes before reading them
+        let token = async_handle
+            .update(Update::Upsert(Account { id: 4, balance: 50 }))
+            .await?;
+        let total = async_handle.query_at_least(token, Query::Total).await?;
+
+        let internal = async_handle.collect_internal_data().await?;
+        assert_eq!(internal.workers.len(), 2);
+        Ok::<_, Error>(total)
+    })?;
+    assert_eq!(total, 850);
+
+    let report = handle.shutdown()?;
+    assert!(report.is_clean(), "{report:?}");
+    println!("total balance: {total}");
+    Ok(())
+}
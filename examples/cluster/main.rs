@@ -0,0 +1,160 @@
+//! Runs an app in two processes on 127.0.0.1, process 0 spawns process 1 from the same binary.
+//!
+//! `cargo run --example cluster`
+
+use std::process::{Child, Command};
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{App, AppConfig, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use serde::{Deserialize, Serialize};
+use timely::dataflow::Scope;
+
+const ADDRESSES: [&str; 2] = ["127.0.0.1:2101", "127.0.0.1:2102"];
+const WORKERS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Query {
+    // the sum of all balances
+    Total,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = i64;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<i64>,
+    ) {
+        match query {
+            Query::Total => {
+                let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+                state.peeks.push(Box::new(move || {
+                    if !trace_beyond(&mut trace, &time) {
+                        return PeekResult::NotReady;
+                    }
+                    responder.respond(total(&mut trace, time));
+                    PeekResult::Done
+                }));
+            }
+        }
+    }
+
+    fn merge(responses: Vec<i64>) -> i64 {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+
+    // half of the workers live in process 1
+    fn route_update(update: Update, workers: usize) -> Route<Update> {
+        let id = match &update {
+            Update::Upsert(account) => account.id,
+            Update::Delete(id) => *id,
+        };
+        Route::Worker(id as usize % workers, update)
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+fn config(process: usize) -> AppConfig {
+    let addresses = ADDRESSES.iter().map(|a| a.to_string()).collect();
+    AppConfig::new(WORKERS).cluster(process, addresses)
+}
+
+fn spawn_process(process: usize) -> Child {
+    let exe = std::env::current_exe().unwrap();
+    Command::new(exe).arg(process.to_string()).spawn().unwrap()
+}
+
+fn main() {
+    if let Some(process) = std::env::args().nth(1) {
+        let process: usize = process.parse().expect("invalid process");
+        let report = BankApp.join_cluster(config(process));
+        assert!(report.is_clean(), "{report:?}");
+        return;
+    }
+
+    let mut child = spawn_process(1);
+    let handle = BankApp.start_cluster(config(0));
+    let updates = (0..10)
+        .map(|id| Update::Upsert(Account { id, balance: 100 }))
+        .collect();
+    handle.update_batch(updates);
+    assert_eq!(handle.query(Query::Total), 1000);
+
+    handle.update(Update::Delete(3));
+    handle.update(Update::Upsert(Account { id: 4, balance: 50 }));
+    let total = handle.query(Query::Total);
+    assert_eq!(total, 850);
+
+    let report = handle.shutdown().unwrap();
+    assert!(report.is_clean(), "{report:?}");
+    assert!(child.wait().unwrap().success());
+    println!("total balance: {total}");
+}
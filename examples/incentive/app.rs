@@ -1,6 +1,6 @@
-use crossbeam::channel::Sender;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertRef};
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, SysTime, WorkerState};
 use differential_dataflow::operators::arrange::{ArrangeByKey, ArrangeBySelf};
 use differential_dataflow::trace::{Cursor, TraceReader};
 use timely::dataflow::Scope;
@@ -10,11 +10,21 @@ use timely::PartialOrder;
 use crate::dataflows::*;
 use crate::error::Error;
 use crate::models::*;
-use crate::typedef::{ErrorTrace, SalesMonthKey, SalesMonthRangeKey, SalesRevenueAccuTrace};
+use crate::typedef::{
+    BelongingTrace, ErrorTrace, SalesMonthKey, SalesMonthRangeKey, SalesRevenueAccuTrace,
+    UidMonthKey,
+};
 
 #[derive(Clone)]
 pub struct IncentiveApp;
 
+/// The inputs allocated by the dataflow on every worker.
+pub struct Inputs {
+    belonging: UpsertRef<Belonging>,
+    sales_org: UpsertRef<SalesOrg>,
+    revenue: UpsertRef<Revenue>,
+}
+
 #[derive(Clone)]
 pub struct IncentiveHandle {
     handle: Handle<IncentiveApp>,
@@ -25,24 +35,48 @@ pub enum Query {
     QuerySalesRevenueAccu {
         sales_ldap: String,
         month: Month,
-        sender: Sender<Result<i64, Vec<Error>>>,
     },
     QuerySalesRevenueAccuRange {
         sales_ldap: String,
         start_month: Month,
         end_month: Month,
-        sender: Sender<Result<Vec<(Month, i64)>, Vec<Error>>>,
+    },
+    QueryBelonging {
+        uid: u64,
+        month: Month,
     },
 }
 
+/// Each worker only holds some keys, the others respond with nothing found.
+#[derive(Debug)]
+pub enum Response {
+    SalesRevenueAccu(Result<i64, Vec<Error>>),
+    SalesRevenueAccuRange(Result<Vec<(Month, i64)>, Vec<Error>>),
+    Belonging(Option<Belonging>),
+}
+
 #[derive(Clone, Debug)]
 pub enum Update {
     UpsertBelonging(Belonging),
-    DeleteBelonging { uid: u64, month: Month },
+    DeleteBelonging {
+        uid: u64,
+        month: Month,
+    },
     UpsertSalesOrg(SalesOrg),
-    DeleteSalesOrg { sales_ldap: String, month: Month },
+    DeleteSalesOrg {
+        sales_ldap: String,
+        month: Month,
+    },
+    SetLeader {
+        sales_ldap: String,
+        month: Month,
+        leader: Option<String>,
+    },
     UpsertRevenue(Revenue),
-    DeleteRevenue { uid: u64, month: Month },
+    DeleteRevenue {
+        uid: u64,
+        month: Month,
+    },
 }
 
 impl IncentiveHandle {
@@ -51,29 +85,38 @@ impl IncentiveHandle {
         sales_ldap: impl Into<String>,
         month: Month,
     ) -> Result<i64, Vec<Error>> {
-        let (tx, rx) = crossbeam::channel::unbounded();
         let cmd = Query::QuerySalesRevenueAccu {
             sales_ldap: sales_ldap.into(),
             month,
-            sender: tx,
         };
-        self.handle.query(cmd);
-        let mut revenue = 0;
-        let mut errors = vec![];
-        while let Ok(d) = rx.recv() {
-            match d {
-                Ok(d) => {
-                    if d != 0 {
-                        revenue = d;
-                    }
-                }
-                Err(e) => errors.extend(e),
-            }
+        match self.handle.query(cmd) {
+            Response::SalesRevenueAccu(res) => res,
+            res => unreachable!("unexpected response: {res:?}"),
         }
-        if errors.is_empty() {
-            Ok(revenue)
-        } else {
-            Err(errors)
+    }
+
+    pub fn query_sales_revenue_accu_range(
+        &self,
+        sales_ldap: impl Into<String>,
+        start_month: Month,
+        end_month: Month,
+    ) -> Result<Vec<(Month, i64)>, Vec<Error>> {
+        let cmd = Query::QuerySalesRevenueAccuRange {
+            sales_ldap: sales_ldap.into(),
+            start_month,
+            end_month,
+        };
+        match self.handle.query(cmd) {
+            Response::SalesRevenueAccuRange(res) => res,
+            res => unreachable!("unexpected response: {res:?}"),
+        }
+    }
+
+    pub fn query_belonging(&self, uid: u64, month: Month) -> Option<Belonging> {
+        let cmd = Query::QueryBelonging { uid, month };
+        match self.handle.query(cmd) {
+            Response::Belonging(res) => res,
+            res => unreachable!("unexpected response: {res:?}"),
         }
     }
 
@@ -97,6 +140,15 @@ impl IncentiveHandle {
         self.handle.update(cmd);
     }
 
+    pub fn set_leader(&self, sales_ldap: String, month: Month, leader: Option<String>) {
+        let cmd = Update::SetLeader {
+            sales_ldap,
+            month,
+            leader,
+        };
+        self.handle.update(cmd);
+    }
+
     pub fn upsert_revenue(&self, revenue: Revenue) {
         let cmd = Update::UpsertRevenue(revenue);
         self.handle.update(cmd);
@@ -116,24 +168,29 @@ pub fn start(workers: usize) -> IncentiveHandle {
 impl App for IncentiveApp {
     type Query = Query;
     type Update = Update;
+    type Response = Response;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "incentive"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, worker_state: WorkerState<'_>) {
-        let belonging_collection = worker_state
+    fn dataflow<G: Scope<Timestamp = SysTime>>(
+        scope: &mut G,
+        worker_state: WorkerState<'_>,
+    ) -> Inputs {
+        let (belonging_arrange, belonging) = worker_state
             .upsert_input_group
-            .alloc_collection::<Belonging, _>(scope);
-        let sales_org_collection = worker_state
+            .alloc_arranged::<Belonging, _>(scope, worker_state.trace_group);
+        let (sales_org_collection, sales_org) = worker_state
             .upsert_input_group
-            .alloc_collection::<SalesOrg, _>(scope);
-        let revenue_collection = worker_state
+            .alloc_merge_collection::<SalesOrg, _>(scope);
+        let (revenue_collection, revenue) = worker_state
             .upsert_input_group
             .alloc_collection::<Revenue, _>(scope);
 
         let (subordinate_collection, subordinate_error) = subordinate(sales_org_collection);
-        let sales_revenue_collection = sales_revenue(belonging_collection, revenue_collection);
+        let sales_revenue_collection = sales_revenue(&belonging_arrange, revenue_collection);
         let sales_revenue_accu_arrange =
             sales_revenue_accu(subordinate_collection, sales_revenue_collection)
                 .map(|r| ((r.sales_ldap.clone(), r.month.clone()), r))
@@ -144,15 +201,21 @@ impl App for IncentiveApp {
             .trace_group
             .register_trace(sales_revenue_accu_arrange.trace);
         worker_state.trace_group.register_trace(error_arrange.trace);
+        Inputs {
+            belonging,
+            sales_org,
+            revenue,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        mut responder: Responder<Self::Response>,
+    ) {
         match query {
-            Query::QuerySalesRevenueAccu {
-                sales_ldap,
-                month,
-                sender,
-            } => {
+            Query::QuerySalesRevenueAccu { sales_ldap, month } => {
                 let mut trace = state
                     .trace_group
                     .get::<SalesRevenueAccuTrace>()
@@ -171,7 +234,7 @@ impl App for IncentiveApp {
                         } else {
                             Err(errors)
                         };
-                        let _ = sender.send(res);
+                        responder.respond(Response::SalesRevenueAccu(res));
                         PeekResult::Done
                     } else {
                         PeekResult::NotReady
@@ -183,7 +246,6 @@ impl App for IncentiveApp {
                 sales_ldap,
                 start_month,
                 end_month,
-                sender,
             } => {
                 let mut trace = state
                     .trace_group
@@ -203,7 +265,22 @@ impl App for IncentiveApp {
                         } else {
                             Err(errors)
                         };
-                        let _ = sender.send(res);
+                        responder.respond(Response::SalesRevenueAccuRange(res));
+                        PeekResult::Done
+                    } else {
+                        PeekResult::NotReady
+                    }
+                };
+                state.peeks.push(Box::new(task));
+            }
+            Query::QueryBelonging { uid, month } => {
+                // reads the input's arrangement, no dataflow of its own
+                let mut trace = state.trace_group.get::<BelongingTrace>().unwrap().clone();
+                let key = (uid, month);
+                let task = move || {
+                    if trace_beyond(&mut trace, &time) {
+                        let belonging = read_belonging(&mut trace, &time, &key);
+                        responder.respond(Response::Belonging(belonging));
                         PeekResult::Done
                     } else {
                         PeekResult::NotReady
@@ -214,19 +291,92 @@ impl App for IncentiveApp {
         }
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        let mut errors = vec![];
+        let mut responses = responses.into_iter().peekable();
+        match responses.peek() {
+            Some(Response::SalesRevenueAccu(_)) => {
+                let mut revenue = 0;
+                for response in responses {
+                    match response {
+                        Response::SalesRevenueAccu(Ok(d)) => revenue += d,
+                        Response::SalesRevenueAccu(Err(e)) => errors.extend(e),
+                        res => unreachable!("unexpected response: {res:?}"),
+                    }
+                }
+                let res = if errors.is_empty() {
+                    Ok(revenue)
+                } else {
+                    Err(errors)
+                };
+                Response::SalesRevenueAccu(res)
+            }
+            Some(Response::SalesRevenueAccuRange(_)) => {
+                let mut revenues = vec![];
+                for response in responses {
+                    match response {
+                        Response::SalesRevenueAccuRange(Ok(d)) => revenues.extend(d),
+                        Response::SalesRevenueAccuRange(Err(e)) => errors.extend(e),
+                        res => unreachable!("unexpected response: {res:?}"),
+                    }
+                }
+                revenues.sort();
+                let res = if errors.is_empty() {
+                    Ok(revenues)
+                } else {
+                    Err(errors)
+                };
+                Response::SalesRevenueAccuRange(res)
+            }
+            Some(Response::Belonging(_)) => {
+                let mut ret = None;
+                for response in responses {
+                    match response {
+                        Response::Belonging(Some(b)) => ret = Some(b),
+                        Response::Belonging(None) => {}
+                        res => unreachable!("unexpected response: {res:?}"),
+                    }
+                }
+                Response::Belonging(ret)
+            }
+            None => unreachable!("every worker responds"),
+        }
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
+        let inputs = state.app;
+        let group = state.upsert_input_group;
         match update {
-            Update::UpsertBelonging(belonging) => state.upsert_input_group.upsert(belonging),
+            Update::UpsertBelonging(belonging) => {
+                let key = belonging.get_key();
+                group.apply_to(&inputs.belonging, [(key, Some(belonging))])
+            }
             Update::DeleteBelonging { uid, month } => {
-                state.upsert_input_group.delete::<Belonging>((uid, month))
+                group.apply_to(&inputs.belonging, [((uid, month), None)])
+            }
+            Update::UpsertSalesOrg(sales_org) => {
+                let key = sales_org.get_key();
+                let patch = SalesOrgPatch::Upsert(sales_org);
+                group.patch_to(&inputs.sales_org, [(key, patch)])
+            }
+            Update::DeleteSalesOrg { sales_ldap, month } => {
+                let patch = SalesOrgPatch::Delete;
+                group.patch_to(&inputs.sales_org, [((sales_ldap, month), patch)])
+            }
+            Update::SetLeader {
+                sales_ldap,
+                month,
+                leader,
+            } => {
+                let patch = SalesOrgPatch::Leader(leader);
+                group.patch_to(&inputs.sales_org, [((sales_ldap, month), patch)])
+            }
+            Update::UpsertRevenue(revenue) => {
+                let key = revenue.get_key();
+                group.apply_to(&inputs.revenue, [(key, Some(revenue))])
             }
-            Update::UpsertSalesOrg(sales_org) => state.upsert_input_group.upsert(sales_org),
-            Update::DeleteSalesOrg { sales_ldap, month } => state
-                .upsert_input_group
-                .delete::<SalesOrg>((sales_ldap, month)),
-            Update::UpsertRevenue(revenue) => state.upsert_input_group.upsert(revenue),
             Update::DeleteRevenue { uid, month } => {
-                state.upsert_input_group.delete::<Revenue>((uid, month))
+                group.apply_to(&inputs.revenue, [((uid, month), None)])
             }
         }
     }
@@ -279,6 +429,43 @@ pub fn read_key(
     ret
 }
 
+pub fn read_belonging(
+    trace: &mut BelongingTrace,
+    time: &SysTime,
+    key: &UidMonthKey,
+) -> Option<Belonging> {
+    let mut upper = Antichain::new();
+    trace.read_upper(&mut upper);
+    assert!(!upper.less_equal(time));
+    assert!(trace.get_logical_compaction().less_equal(time));
+
+    let mut ret = None;
+    let (mut cursor, storage) = trace.cursor();
+    cursor.seek_key(&storage, key);
+    if cursor.get_key(&storage) == Some(key) {
+        while let Some(val) = cursor.get_val(&storage) {
+            let mut count = 0;
+            cursor.map_times(&storage, |dtime, diff| {
+                if dtime.less_equal(time) {
+                    count += diff;
+                }
+            });
+            assert!(
+                count == 0 || count == 1,
+                "invalid count for key: {:?}, count: {}",
+                key,
+                count
+            );
+            if count == 1 {
+                assert!(ret.is_none(), "more than one belonging for key: {:?}", key);
+                ret = Some(val.clone());
+            }
+            cursor.step_val(&storage);
+        }
+    }
+    ret
+}
+
 pub fn read_key_range(
     trace: &mut SalesRevenueAccuTrace,
     time: &SysTime,
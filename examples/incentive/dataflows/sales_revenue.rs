@@ -1,29 +1,27 @@
 use crate::models::{Belonging, Revenue, SalesRevenue};
-use ddquery::SysDiff;
+use crate::typedef::BelongingTrace;
+use ddquery::{SysDiff, SysTime};
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::arrange::ArrangeByKey;
+use differential_dataflow::operators::arrange::{ArrangeByKey, Arranged};
 use differential_dataflow::operators::*;
 use differential_dataflow::Collection;
 use timely::dataflow::Scope;
 use timely::order::TotalOrder;
 
+// `belonging` is the upsert arrangement, already keyed by (uid, month)
 pub fn sales_revenue<G>(
-    belonging: Collection<G, Belonging, SysDiff>,
+    belonging: &Arranged<G, BelongingTrace>,
     user_revenue: Collection<G, Revenue, SysDiff>,
 ) -> Collection<G, SalesRevenue, SysDiff>
 where
-    G: Scope,
-    G::Timestamp: Lattice + Ord,
+    G: Scope<Timestamp = SysTime>,
 {
-    let belonging_arrange = belonging
-        .map(|b| ((b.uid, b.month), (b.sales_ldap)))
-        .arrange_by_key();
     let user_revenue_arrange = user_revenue
         .map(|u| ((u.uid, u.month), u.revenue))
         .arrange_by_key();
-    belonging_arrange
-        .join_core(&user_revenue_arrange, |(_, month), sales_ldap, revenue| {
-            Some(((sales_ldap.clone(), month.clone()), *revenue))
+    belonging
+        .join_core(&user_revenue_arrange, |(_, month), belonging, revenue| {
+            Some(((belonging.sales_ldap.clone(), month.clone()), *revenue))
         })
         .arrange_by_key()
         .reduce_named("Reduce", |_key, input, output| {
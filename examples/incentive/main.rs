@@ -29,4 +29,16 @@ fn main() {
     handle.upsert_belonging(Belonging::new(2, "s2", 202401));
     let res = handle.query_sales_revenue_accu("s2", 202401);
     assert_eq!(res, Ok(8));
+
+    let res = handle.query_belonging(2, 202401);
+    assert_eq!(res, Some(Belonging::new(2, "s2", 202401)));
+    let res = handle.query_belonging(3, 202401);
+    assert_eq!(res, None);
+
+    // only the leader changes, s2 loses s1's revenue
+    handle.set_leader("s1".to_string(), 202401, None);
+    let res = handle.query_sales_revenue_accu("s2", 202401);
+    assert_eq!(res, Ok(5));
+    let res = handle.query_sales_revenue_accu("s1", 202401);
+    assert_eq!(res, Ok(3));
 }
@@ -1,4 +1,4 @@
-use ddquery::timely_util::upsert_input::UpsertInput;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertMerge};
 use serde::{Deserialize, Serialize};
 
 /// format: yyyyMM, e.g. 202405
@@ -43,6 +43,28 @@ impl UpsertInput for SalesOrg {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SalesOrgPatch {
+    Upsert(SalesOrg),
+    Delete,
+    // keeps the rest of the sales org, nothing happens if there is none
+    Leader(Option<String>),
+}
+
+impl UpsertMerge for SalesOrg {
+    type Patch = SalesOrgPatch;
+    fn merge(old: Option<&Self>, patch: Self::Patch) -> Option<Self> {
+        match patch {
+            SalesOrgPatch::Upsert(sales_org) => Some(sales_org),
+            SalesOrgPatch::Delete => None,
+            SalesOrgPatch::Leader(leader) => old.map(|s| SalesOrg {
+                leader,
+                ..s.clone()
+            }),
+        }
+    }
+}
+
 impl SalesOrg {
     pub fn new<T>(sales_ldap: impl Into<String>, leader: Option<T>, month: Month) -> Self
     where
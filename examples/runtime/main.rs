@@ -0,0 +1,204 @@
+//! Hosts two apps on one worker pool, the report app reads the accounts of the bank app.
+//!
+//! `cargo run --example runtime`
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{App, AppConfig, PeekResult, Responder, Runtime, SysDiff, SysTime, WorkerState};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use timely::dataflow::Scope;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug)]
+enum Query {
+    // the sum of all balances
+    Total,
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = i64;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<i64>,
+    ) {
+        match query {
+            Query::Total => {
+                let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+                state.peeks.push(Box::new(move || {
+                    if !trace_beyond(&mut trace, &time) {
+                        return PeekResult::NotReady;
+                    }
+                    responder.respond(total(&mut trace, time));
+                    PeekResult::Done
+                }));
+            }
+        }
+    }
+
+    fn merge(responses: Vec<i64>) -> i64 {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+/// Counts the accounts holding at least the queried balance, hosted next to `BankApp` by a
+/// `Runtime` to read its accounts.
+#[derive(Clone)]
+struct ReportApp;
+
+impl App for ReportApp {
+    type Query = i64;
+    type Update = ();
+    type Response = usize;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "report"
+    }
+
+    // no dataflow of its own, `BankApp` arranges the accounts
+    fn dataflow<G: Scope<Timestamp = SysTime>>(_scope: &mut G, _state: WorkerState<'_>) {}
+
+    fn handle_query(
+        min_balance: i64,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<usize>,
+    ) {
+        let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+        state.peeks.push(Box::new(move || {
+            if !trace_beyond(&mut trace, &time) {
+                return PeekResult::NotReady;
+            }
+            responder.respond(count(&mut trace, time, min_balance));
+            PeekResult::Done
+        }));
+    }
+
+    fn merge(responses: Vec<usize>) -> usize {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(_update: (), _state: WorkerState<'_>) {}
+}
+
+/// The number of accounts in this worker's part of `trace` holding at least `min_balance`.
+fn count(trace: &mut AccountTrace, time: SysTime, min_balance: i64) -> usize {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            let mut diffs = 0;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    diffs += diff.into_owned();
+                }
+            });
+            if diffs > 0 && balance >= min_balance {
+                ret += 1;
+            }
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+fn main() {
+    let runtime = Runtime::builder(AppConfig::new(2))
+        .app(&BankApp)
+        .app(&ReportApp)
+        .start();
+    let bank = runtime.handle::<BankApp>("bank");
+    let report = runtime.handle::<ReportApp>("report");
+    let updates = (0..10)
+        .map(|id| {
+            Update::Upsert(Account {
+                id,
+                balance: id as i64 * 100,
+            })
+        })
+        .collect();
+    bank.update_batch(updates);
+    bank.update(Update::Delete(9));
+    assert_eq!(bank.query(Query::Total), 3600);
+
+    // the apps share the time, a token of one app orders the queries of the other
+    let token = bank.update(Update::Upsert(Account {
+        id: 1,
+        balance: 800,
+    }));
+    let large = report.query_at_least(token, 500);
+    assert_eq!(large, 5);
+
+    let shutdown = runtime.shutdown().unwrap();
+    assert!(shutdown.is_clean(), "{shutdown:?}");
+    println!("accounts holding at least 500: {large}");
+}
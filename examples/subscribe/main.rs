@@ -0,0 +1,160 @@
+//! Keeps a cache of the large accounts up to date by subscribing to the account trace.
+//!
+//! `cargo run --example subscribe`
+
+use std::collections::BTreeMap;
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{App, PeekResult, Responder, SysDiff, SysTime, WorkerState};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use timely::dataflow::Scope;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug)]
+enum Query {
+    // the sum of all balances
+    Total,
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = i64;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<i64>,
+    ) {
+        match query {
+            Query::Total => {
+                let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+                state.peeks.push(Box::new(move || {
+                    if !trace_beyond(&mut trace, &time) {
+                        return PeekResult::NotReady;
+                    }
+                    responder.respond(total(&mut trace, time));
+                    PeekResult::Done
+                }));
+            }
+        }
+    }
+
+    fn merge(responses: Vec<i64>) -> i64 {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+/// Keep the latest balance of each account in `large`, the retraction of an older balance may
+/// come after its replacement.
+fn apply(large: &mut BTreeMap<u64, i64>, updates: Vec<((u64, Account), SysTime, SysDiff)>) {
+    for ((id, account), _, diff) in updates {
+        if diff > 0 {
+            large.insert(id, account.balance);
+        } else if large.get(&id) == Some(&account.balance) {
+            large.remove(&id);
+        }
+    }
+}
+
+fn main() {
+    let handle = BankApp.start(2);
+    let updates = (0..10)
+        .map(|id| {
+            Update::Upsert(Account {
+                id,
+                balance: id as i64 * 100,
+            })
+        })
+        .collect();
+    handle.update_batch(updates);
+
+    // a snapshot of the accounts holding at least 500, then their changes
+    let mut sub =
+        handle.subscribe::<AccountTrace, _, _>(|_: &u64, account: &Account| account.balance >= 500);
+    let mut large = BTreeMap::new();
+    apply(&mut large, sub.next().unwrap());
+    assert_eq!(large.len(), 5);
+
+    handle.update(Update::Delete(9));
+    handle.update(Update::Upsert(Account {
+        id: 1,
+        balance: 800,
+    }));
+    let token = handle.update(Update::Upsert(Account { id: 5, balance: 0 }));
+    while sub.upper() <= token.time() {
+        apply(&mut large, sub.next().unwrap());
+    }
+    assert_eq!(
+        large,
+        BTreeMap::from([(1, 800), (6, 600), (7, 700), (8, 800)])
+    );
+
+    let report = handle.shutdown().unwrap();
+    assert!(report.is_clean(), "{report:?}");
+    println!("large accounts: {large:?}");
+}
@@ -0,0 +1,134 @@
+//! Reads the state as of earlier writes, kept readable by the compaction policy.
+//!
+//! `cargo run --example time_travel`
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{
+    App, AppConfig, CompactionPolicy, Error, PeekResult, Responder, SysDiff, SysTime, WorkerState,
+};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use timely::dataflow::Scope;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug)]
+enum Query {
+    // the sum of all balances
+    Total,
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = i64;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<i64>,
+    ) {
+        match query {
+            Query::Total => {
+                let mut trace = state.trace_group.get::<AccountTrace>().unwrap().clone();
+                state.peeks.push(Box::new(move || {
+                    if !trace_beyond(&mut trace, &time) {
+                        return PeekResult::NotReady;
+                    }
+                    responder.respond(total(&mut trace, time));
+                    PeekResult::Done
+                }));
+            }
+        }
+    }
+
+    fn merge(responses: Vec<i64>) -> i64 {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+fn main() {
+    // the times of the last two ticks before the latest one stay readable
+    let config = AppConfig::new(2).compaction(CompactionPolicy::RetainTicks(2));
+    let handle = BankApp.start_with(config);
+    let updates = (0..10)
+        .map(|id| Update::Upsert(Account { id, balance: 100 }))
+        .collect();
+    let opened = handle.update_batch(updates).time();
+    handle.update(Update::Delete(3));
+    handle.update(Update::Upsert(Account { id: 4, balance: 50 }));
+    assert_eq!(handle.query(Query::Total), 850);
+    let total = handle.query_as_of(Query::Total, opened);
+    assert_eq!(total, 1000);
+
+    // one more tick compacts the time the accounts were opened at
+    handle.update(Update::Delete(4));
+    let err = handle.try_query_as_of(Query::Total, opened).unwrap_err();
+    assert!(matches!(err, Error::TimeCompacted { time, .. } if time == opened));
+
+    let report = handle.shutdown().unwrap();
+    assert!(report.is_clean(), "{report:?}");
+    println!("total balance when opened: {total}");
+}
@@ -28,8 +28,24 @@ macro_rules! gen_tpch_app {
                     batches
                 }
             }
+
+            /// The inputs the query's dataflow allocated, one per table.
+            pub struct Inputs {
+                $([<$name:snake>]: ddquery::timely_util::dd_input::InputRef<$name>,)*
+            }
+
+            impl Update {
+                fn push_into(self, state: WorkerState<'_, Inputs>) {
+                    match self {
+                        $(Update::$name(v) => {
+                            state.input_group.insert_batch_to(&state.app.[<$name:snake>], v);
+                        })*
+                    }
+                }
+            }
         }
 
+        #[derive(Clone)]
         pub enum Update {
             $($name(Vec<$name>)),*
         }
@@ -43,20 +59,32 @@ macro_rules! gen_tpch_app {
         )*
 
         impl Update {
-            fn push_into(self, state: WorkerState<'_>)  {
+            /// spread the batch over all workers, each worker pushes its part into the input
+            fn route(self, workers: usize) -> Route<Update> {
                 match self {
-                    $(Update::$name(v) => {state.input_group.insert_batch::<$name>(v);})*
+                    $(Update::$name(v) => {
+                        let mut parts: Vec<Vec<$name>> = (0..workers).map(|_| vec![]).collect();
+                        for (i, d) in v.into_iter().enumerate() {
+                            parts[i % workers].push(d);
+                        }
+                        let parts = parts
+                            .into_iter()
+                            .enumerate()
+                            .filter(|(_, p)| !p.is_empty())
+                            .map(|(idx, p)| (idx, Update::$name(p)))
+                            .collect();
+                        Route::Split(parts)
+                    })*
                 }
             }
         }
 
         #[derive(Clone)]
-        pub struct Query {
-            pub sender: Sender<Vec<Answer>>,
-        }
+        pub struct Query;
 
         impl Query {
-            fn query(self, time: SysTime, state: WorkerState<'_>) {
+            /// every worker responds with its part of the answer
+            fn query(self, time: SysTime, state: WorkerState<'_, Inputs>, mut responder: Responder<Vec<Answer>>) {
                 let mut trace = state
                     .trace_group
                     .get::<AnswerTrace<Answer>>()
@@ -66,7 +94,7 @@ macro_rules! gen_tpch_app {
                 let task = move || {
                     if trace_beyond(&mut trace, &time) {
                         let data = collect_key_trace(&mut trace, &time);
-                        let _ = self.sender.send(data);
+                        responder.respond(data);
                         PeekResult::Done
                     } else {
                         PeekResult::NotReady
@@ -1,7 +1,6 @@
 use chrono::{Days, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -16,20 +15,14 @@ gen_tpch_app!(01, LineItem);
 
 impl TpchResults for Q01 {
     fn results(handle: &Handle<Q01>) -> Vec<Q01Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            for mut e in d {
-                e.sum_base_price.rescale(2);
-                e.sum_disc_price.rescale(2);
-                e.sum_charge.rescale(2);
-                e.avg_qty.rescale(2);
-                e.avg_disc.rescale(2);
-                e.avg_price.rescale(2);
-                res.push(e);
-            }
+        let mut res = handle.query(Query);
+        for e in &mut res {
+            e.sum_base_price.rescale(2);
+            e.sum_disc_price.rescale(2);
+            e.sum_charge.rescale(2);
+            e.avg_qty.rescale(2);
+            e.avg_disc.rescale(2);
+            e.avg_price.rescale(2);
         }
         res.sort_by(|l, r| (l.return_flag, l.line_status).cmp(&(r.return_flag, r.line_status)));
         res
@@ -39,16 +32,18 @@ impl TpchResults for Q01 {
 impl App for Q01 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q01"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         let date = NaiveDate::from_ymd_opt(1998, 12, 01).unwrap();
         let date = date - Days::new(90);
         let one = Decimal::from(1);
-        let lineitem = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
         let arranged = lineitem
             .explode(move |li| {
                 let disc_price = li.extended_price * (one - li.discount);
@@ -74,15 +69,31 @@ impl App for Q01 {
 
         let trace: AnswerTrace<Q01Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            line_item: line_item_input,
+        }
+    }
+
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer((return_flag, line_status): (char, char), sum: Sum) -> Q01Answer {
@@ -1,8 +1,7 @@
 use std::cmp::Reverse;
 
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -17,13 +16,7 @@ gen_tpch_app!(02, Part, Supplier, PartSupp, Nation, Region);
 
 impl TpchResults for Q02 {
     fn results(handle: &Handle<Q02>) -> Vec<Q02Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by(|x, y| {
             (Reverse(&x.s_acctbal), &x.n_name, &x.s_name, x.p_partkey).cmp(&(
                 Reverse(&y.s_acctbal),
@@ -40,43 +33,36 @@ impl TpchResults for Q02 {
 impl App for Q02 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q02"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. SIZE = 15;
         // 2. TYPE = BRASS;
         // 3. REGION = EUROPE
 
-        let part = state
-            .input_group
-            .alloc_collection::<Part, _>(scope)
+        let (part, part_input) = state.input_group.alloc_collection::<Part, _>(scope);
+        let part = part
             .filter(|x| x.size == 15 && x.typ.ends_with("BRASS"))
             .map(|p| (p.part_key, p));
-        let supplier = state
-            .input_group
-            .alloc_collection::<Supplier, _>(scope)
-            .map(|s| (s.nation_key, s));
-        let part_supp = state
-            .input_group
-            .alloc_collection::<PartSupp, _>(scope)
-            .map(|ps| (ps.supp_key, ps));
-        let nation = state
-            .input_group
-            .alloc_collection::<Nation, _>(scope)
-            .map(|s| (s.region_key, s));
-        let region = state
-            .input_group
-            .alloc_collection::<Region, _>(scope)
-            .flat_map(|x| {
-                if x.name == "EUROPE" {
-                    Some((x.region_key, x))
-                } else {
-                    None
-                }
-            });
+        let (supplier, supplier_input) = state.input_group.alloc_collection::<Supplier, _>(scope);
+        let supplier = supplier.map(|s| (s.nation_key, s));
+        let (part_supp, part_supp_input) = state.input_group.alloc_collection::<PartSupp, _>(scope);
+        let part_supp = part_supp.map(|ps| (ps.supp_key, ps));
+        let (nation, nation_input) = state.input_group.alloc_collection::<Nation, _>(scope);
+        let nation = nation.map(|s| (s.region_key, s));
+        let (region, region_input) = state.input_group.alloc_collection::<Region, _>(scope);
+        let region = region.flat_map(|x| {
+            if x.name == "EUROPE" {
+                Some((x.region_key, x))
+            } else {
+                None
+            }
+        });
 
         let combined = nation
             .join_map(&region, |_, nation, _| (nation.nation_key, nation.clone()))
@@ -113,15 +99,35 @@ impl App for Q02 {
 
         let trace: AnswerTrace<Q02Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            part: part_input,
+            supplier: supplier_input,
+            part_supp: part_supp_input,
+            nation: nation_input,
+            region: region_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Hash)]
@@ -1,9 +1,8 @@
 use std::cmp::Reverse;
 
 use chrono::NaiveDate;
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -18,13 +17,7 @@ gen_tpch_app!(03, Customer, Order, LineItem);
 
 impl TpchResults for Q03 {
     fn results(handle: &Handle<Q03>) -> Vec<Q03Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by(|x, y| {
             (Reverse(&x.revenue), &x.o_orderdate).cmp(&(Reverse(&y.revenue), &y.o_orderdate))
         });
@@ -39,31 +32,30 @@ impl TpchResults for Q03 {
 impl App for Q03 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q03"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. SEGMENT = BUILDING;
         // 2. DATE = 1995-03-15.
 
         let date = NaiveDate::from_ymd_opt(1995, 3, 15).unwrap();
         let one = Decimal::from(1);
-        let customer = state
-            .input_group
-            .alloc_collection::<Customer, _>(scope)
+        let (customer, customer_input) = state.input_group.alloc_collection::<Customer, _>(scope);
+        let customer = customer
             .filter(|x| x.mktsegment == "BUILDING")
             .map(|x| (x.cust_key, x));
-        let orders = state
-            .input_group
-            .alloc_collection::<Order, _>(scope)
+        let (orders, order_input) = state.input_group.alloc_collection::<Order, _>(scope);
+        let orders = orders
             .filter(move |x| x.order_date < date)
             .map(|x| (x.cust_key, x));
 
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem
             .filter(move |x| x.ship_date > date)
             .map(|x| (x.order_key, x));
 
@@ -82,15 +74,33 @@ impl App for Q03 {
 
         let trace: AnswerTrace<Q03Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            customer: customer_input,
+            order: order_input,
+            line_item: line_item_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer(
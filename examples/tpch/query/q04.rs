@@ -1,7 +1,6 @@
 use chrono::{Months, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
 use timely::dataflow::Scope;
@@ -13,13 +12,7 @@ gen_tpch_app!(04, Order, LineItem);
 
 impl TpchResults for Q04 {
     fn results(handle: &Handle<Q04>) -> Vec<Q04Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by(|x, y| x.o_orderpriority.cmp(&y.o_orderpriority));
         res
     }
@@ -28,25 +21,25 @@ impl TpchResults for Q04 {
 impl App for Q04 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q04"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. DATE = 1993-07-01.
 
         let date = NaiveDate::from_ymd_opt(1993, 7, 1).unwrap();
         let end_date = date + Months::new(3);
-        let orders = state
-            .input_group
-            .alloc_collection::<Order, _>(scope)
+        let (orders, order_input) = state.input_group.alloc_collection::<Order, _>(scope);
+        let orders = orders
             .filter(move |x| x.order_date >= date && x.order_date < end_date)
             .map(|x| (x.order_key, x));
 
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem
             .filter(move |x| x.commit_date < x.receipt_date)
             .map(|x| x.order_key)
             .distinct_total_core::<SysDiff>();
@@ -60,15 +53,32 @@ impl App for Q04 {
 
         let trace: AnswerTrace<Q04Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            order: order_input,
+            line_item: line_item_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer(o_orderpriority: String, order_count: i64) -> Q04Answer {
@@ -1,9 +1,8 @@
 use std::cmp::Reverse;
 
 use chrono::{Months, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -18,13 +17,7 @@ gen_tpch_app!(05, Customer, Order, LineItem, Supplier, Nation, Region);
 
 impl TpchResults for Q05 {
     fn results(handle: &Handle<Self>) -> Vec<Self::Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by_key(|x| Reverse(x.revenue));
         for d in &mut res {
             d.revenue.rescale(2);
@@ -36,12 +29,14 @@ impl TpchResults for Q05 {
 impl App for Q05 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q05"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. REGION = ASIA;
         // 2. DATE = 1994-01-01.
 
@@ -49,38 +44,28 @@ impl App for Q05 {
         let end_date = date + Months::new(12);
         let one = Decimal::from(1);
 
-        let supplier = state
-            .input_group
-            .alloc_collection::<Supplier, _>(scope)
-            .map(|s| (s.nation_key, s));
-        let nation = state
-            .input_group
-            .alloc_collection::<Nation, _>(scope)
-            .map(|s| (s.region_key, s));
-        let region = state
-            .input_group
-            .alloc_collection::<Region, _>(scope)
-            .flat_map(|x| {
-                if x.name == "ASIA" {
-                    Some((x.region_key, x))
-                } else {
-                    None
-                }
-            });
-
-        let customer = state
-            .input_group
-            .alloc_collection::<Customer, _>(scope)
-            .map(|x| (x.cust_key, x));
-        let orders = state
-            .input_group
-            .alloc_collection::<Order, _>(scope)
+        let (supplier, supplier_input) = state.input_group.alloc_collection::<Supplier, _>(scope);
+        let supplier = supplier.map(|s| (s.nation_key, s));
+        let (nation, nation_input) = state.input_group.alloc_collection::<Nation, _>(scope);
+        let nation = nation.map(|s| (s.region_key, s));
+        let (region, region_input) = state.input_group.alloc_collection::<Region, _>(scope);
+        let region = region.flat_map(|x| {
+            if x.name == "ASIA" {
+                Some((x.region_key, x))
+            } else {
+                None
+            }
+        });
+
+        let (customer, customer_input) = state.input_group.alloc_collection::<Customer, _>(scope);
+        let customer = customer.map(|x| (x.cust_key, x));
+        let (orders, order_input) = state.input_group.alloc_collection::<Order, _>(scope);
+        let orders = orders
             .filter(move |x| x.order_date >= date && x.order_date < end_date)
             .map(|x| (x.cust_key, x));
 
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem
             .filter(move |x| x.ship_date > date)
             .map(|x| (x.order_key, x));
 
@@ -110,15 +95,36 @@ impl App for Q05 {
 
         let trace: AnswerTrace<Q05Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            supplier: supplier_input,
+            nation: nation_input,
+            region: region_input,
+            customer: customer_input,
+            order: order_input,
+            line_item: line_item_input,
+        }
+    }
+
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer(n_name: String, sum: Sum) -> Q05Answer {
@@ -1,7 +1,6 @@
 use chrono::{Months, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -16,13 +15,7 @@ gen_tpch_app!(06, LineItem);
 
 impl TpchResults for Q06 {
     fn results(handle: &Handle<Self>) -> Vec<Self::Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         for d in &mut res {
             d.revenue.rescale(2);
         }
@@ -33,12 +26,14 @@ impl TpchResults for Q06 {
 impl App for Q06 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q06"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. DATE = 1994-01-01;
         // 2. DISCOUNT = 0.06;
         // 3. QUANTITY = 24.
@@ -50,9 +45,8 @@ impl App for Q06 {
         let discount_a = discount - shift;
         let discount_b = discount + shift;
 
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem
             .filter(move |x| {
                 x.ship_date >= date
                     && x.ship_date < end_date
@@ -70,15 +64,31 @@ impl App for Q06 {
 
         let trace: AnswerTrace<Q06Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            line_item: line_item_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer(_: (), sum: Sum) -> Q06Answer {
@@ -1,7 +1,6 @@
 use chrono::{Datelike, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -16,13 +15,7 @@ gen_tpch_app!(07, Supplier, LineItem, Order, Customer, Nation);
 
 impl TpchResults for Q07 {
     fn results(handle: &Handle<Self>) -> Vec<Self::Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by(|x, y| {
             (&x.supp_nation, &x.cust_nation, x.l_year).cmp(&(
                 &y.supp_nation,
@@ -40,12 +33,14 @@ impl TpchResults for Q07 {
 impl App for Q07 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q07"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. NATION1 = FRANCE;
         // 2. NATION2 = GERMANY
 
@@ -53,33 +48,26 @@ impl App for Q07 {
         let end_date = NaiveDate::from_ymd_opt(1996, 12, 31).unwrap();
         let one = Decimal::from(1);
 
-        let nation = state
-            .input_group
-            .alloc_collection::<Nation, _>(scope)
-            .filter(|x| x.name == "FRANCE" || x.name == "GERMANY");
+        let (nation, nation_input) = state.input_group.alloc_collection::<Nation, _>(scope);
+        let nation = nation.filter(|x| x.name == "FRANCE" || x.name == "GERMANY");
 
         let nation_ids = nation.map(|x| x.nation_key);
 
-        let supplier = state
-            .input_group
-            .alloc_collection::<Supplier, _>(scope)
+        let (supplier, supplier_input) = state.input_group.alloc_collection::<Supplier, _>(scope);
+        let supplier = supplier
             .map(|x| (x.nation_key, x))
             .semijoin(&nation_ids)
             .map(|(_, s)| (s.supp_key, s));
-        let customer = state
-            .input_group
-            .alloc_collection::<Customer, _>(scope)
+        let (customer, customer_input) = state.input_group.alloc_collection::<Customer, _>(scope);
+        let customer = customer
             .map(|x| (x.nation_key, x))
             .semijoin(&nation_ids)
             .map(|(_, x)| (x.cust_key, x));
-        let orders = state
-            .input_group
-            .alloc_collection::<Order, _>(scope)
-            .map(|x| (x.cust_key, x));
-
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
+        let (orders, order_input) = state.input_group.alloc_collection::<Order, _>(scope);
+        let orders = orders.map(|x| (x.cust_key, x));
+
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem
             .filter(move |x| x.ship_date >= date && x.ship_date <= end_date)
             .map(|x| (x.order_key, x));
 
@@ -128,15 +116,35 @@ impl App for Q07 {
 
         let trace: AnswerTrace<Q07Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            nation: nation_input,
+            supplier: supplier_input,
+            customer: customer_input,
+            order: order_input,
+            line_item: line_item_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer((from, to, year): (String, String, i32), sum: Sum) -> Q07Answer {
@@ -1,7 +1,6 @@
 use chrono::{Datelike, NaiveDate};
-use crossbeam::channel::Sender;
 use ddquery::timely_util::{collect_key_trace, trace_beyond};
-use ddquery::{App, Handle, PeekResult, SysDiff, SysTime, WorkerState};
+use ddquery::{App, Handle, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
 use differential_dataflow::difference::{IsZero, Multiply, Semigroup};
 use differential_dataflow::operators::arrange::ArrangeBySelf;
 use differential_dataflow::operators::*;
@@ -16,13 +15,7 @@ gen_tpch_app!(08, Part, Supplier, LineItem, Order, Customer, Nation, Region);
 
 impl TpchResults for Q08 {
     fn results(handle: &Handle<Self>) -> Vec<Self::Answer> {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        handle.query(Query { sender: tx });
-
-        let mut res = vec![];
-        while let Ok(d) = rx.recv() {
-            res.extend(d);
-        }
+        let mut res = handle.query(Query);
         res.sort_by_key(|x| x.o_year);
         for d in &mut res {
             d.mkt_share.rescale(2);
@@ -34,12 +27,14 @@ impl TpchResults for Q08 {
 impl App for Q08 {
     type Query = Query;
     type Update = Update;
+    type Response = Vec<Answer>;
+    type State = Inputs;
 
     fn name(&self) -> &str {
         "q08"
     }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) -> Inputs {
         // 1. NATION = BRAZIL;
         // 2. REGION = AMERICA;
         // 3. TYPE = ECONOMY ANODIZED STEEL.
@@ -48,24 +43,19 @@ impl App for Q08 {
         let end_date = NaiveDate::from_ymd_opt(1996, 12, 31).unwrap();
         let one = Decimal::from(1);
 
-        let region = state
-            .input_group
-            .alloc_collection::<Region, _>(scope)
-            .flat_map(|x| {
-                if x.name == "AMERICA" {
-                    Some(x.region_key)
-                } else {
-                    None
-                }
-            });
-        let nation = state.input_group.alloc_collection::<Nation, _>(scope);
-        let customer = state
-            .input_group
-            .alloc_collection::<Customer, _>(scope)
-            .map(|x| (x.nation_key, x));
-        let orders = state
-            .input_group
-            .alloc_collection::<Order, _>(scope)
+        let (region, region_input) = state.input_group.alloc_collection::<Region, _>(scope);
+        let region = region.flat_map(|x| {
+            if x.name == "AMERICA" {
+                Some(x.region_key)
+            } else {
+                None
+            }
+        });
+        let (nation, nation_input) = state.input_group.alloc_collection::<Nation, _>(scope);
+        let (customer, customer_input) = state.input_group.alloc_collection::<Customer, _>(scope);
+        let customer = customer.map(|x| (x.nation_key, x));
+        let (orders, order_input) = state.input_group.alloc_collection::<Order, _>(scope);
+        let orders = orders
             .filter(move |x| x.order_date >= date && x.order_date <= end_date)
             .map(|x| (x.cust_key, x));
 
@@ -77,20 +67,16 @@ impl App for Q08 {
 
         let y = orders.semijoin(&x).map(|(_, o)| (o.order_key, o));
 
-        let part = state
-            .input_group
-            .alloc_collection::<Part, _>(scope)
+        let (part, part_input) = state.input_group.alloc_collection::<Part, _>(scope);
+        let part = part
             .filter(|x| x.typ == "ECONOMY ANODIZED STEEL")
             .map(|x| (x.part_key, x));
 
-        let lineitem = state
-            .input_group
-            .alloc_collection::<LineItem, _>(scope)
-            .map(|x| (x.part_key, x));
+        let (lineitem, line_item_input) = state.input_group.alloc_collection::<LineItem, _>(scope);
+        let lineitem = lineitem.map(|x| (x.part_key, x));
 
-        let supplier = state
-            .input_group
-            .alloc_collection::<Supplier, _>(scope)
+        let (supplier, supplier_input) = state.input_group.alloc_collection::<Supplier, _>(scope);
+        let supplier = supplier
             .map(|x| (x.nation_key, x))
             .map(|(_, s)| (s.supp_key, s));
 
@@ -131,15 +117,37 @@ impl App for Q08 {
 
         let trace: AnswerTrace<Q08Answer> = arranged.trace;
         state.trace_group.register_trace(trace);
+        Inputs {
+            region: region_input,
+            customer: customer_input,
+            order: order_input,
+            part: part_input,
+            line_item: line_item_input,
+            supplier: supplier_input,
+            nation: nation_input,
+        }
     }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>) {
-        query.query(time, state);
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Inputs>,
+        responder: Responder<Self::Response>,
+    ) {
+        query.query(time, state, responder);
     }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>) {
+    fn merge(responses: Vec<Self::Response>) -> Self::Response {
+        responses.into_iter().flatten().collect()
+    }
+
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Inputs>) {
         update.push_into(state);
     }
+
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        update.route(workers)
+    }
 }
 
 fn to_answer(year: &i32, year_volume: &Sum, specific_volume: &Sum) -> Q08Answer {
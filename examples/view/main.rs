@@ -0,0 +1,168 @@
+//! Adds a view to a running app and drops it again, the view imports the app's input.
+//!
+//! `cargo run --example view`
+
+use ddquery::timely_util::trace_beyond;
+use ddquery::timely_util::upsert_input::{UpsertInput, UpsertTrace};
+use ddquery::{App, Handle, PeekResult, Responder, SysDiff, SysTime, WorkerState};
+use differential_dataflow::operators::arrange::ArrangeByKey;
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use timely::dataflow::Scope;
+
+/// The accounts holding at least 500, see `install_large`.
+const LARGE_VIEW: &str = "large";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Account {
+    id: u64,
+    balance: i64,
+}
+
+impl UpsertInput for Account {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.id
+    }
+}
+
+type AccountTrace = UpsertTrace<Account, SysTime, SysDiff>;
+
+#[derive(Clone)]
+struct BankApp;
+
+#[derive(Clone, Debug)]
+enum Query {
+    // the sum of all balances
+    Total,
+    // the sum of the balances in the large view
+    LargeTotal,
+}
+
+#[derive(Clone, Debug)]
+enum Update {
+    Upsert(Account),
+    Delete(u64),
+}
+
+impl App for BankApp {
+    type Query = Query;
+    type Update = Update;
+    /// `None` if the queried view is not installed.
+    type Response = Option<i64>;
+    type State = ();
+
+    fn name(&self) -> &str {
+        "bank"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>) {
+        state
+            .upsert_input_group
+            .alloc_arranged::<Account, _>(scope, state.trace_group);
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_>,
+        mut responder: Responder<Option<i64>>,
+    ) {
+        let trace = match query {
+            Query::Total => state.trace_group.get::<AccountTrace>(),
+            Query::LargeTotal => state.trace_group.get_named::<AccountTrace>(LARGE_VIEW),
+        };
+        let Ok(trace) = trace else {
+            responder.respond(None);
+            return;
+        };
+        let mut trace = trace.clone();
+        state.peeks.push(Box::new(move || {
+            if !trace_beyond(&mut trace, &time) {
+                return PeekResult::NotReady;
+            }
+            responder.respond(Some(total(&mut trace, time)));
+            PeekResult::Done
+        }));
+    }
+
+    fn merge(responses: Vec<Option<i64>>) -> Option<i64> {
+        responses.into_iter().sum()
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_>) {
+        match update {
+            Update::Upsert(account) => state.upsert_input_group.upsert(account),
+            Update::Delete(id) => state.upsert_input_group.delete::<Account>(id),
+        }
+    }
+}
+
+/// The sum of the balances in this worker's part of `trace`.
+fn total(trace: &mut AccountTrace, time: SysTime) -> i64 {
+    let mut ret = 0;
+    let (mut cursor, storage) = trace.cursor();
+    while cursor.key_valid(&storage) {
+        while let Some(account) = cursor.get_val(&storage) {
+            let balance = account.into_owned().balance;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    ret += balance * diff.into_owned();
+                }
+            });
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+/// Build the large view on every worker, it reads the accounts written before it was installed.
+fn install_large(handle: &Handle<BankApp>) {
+    handle.install_view(LARGE_VIEW, |scope, state| {
+        let accounts = state
+            .upsert_input_group
+            .import_arranged::<Account, _>(scope)
+            .unwrap();
+        let large = accounts
+            .as_collection(|id, account| (*id, account.clone()))
+            .filter(|(_, account)| account.balance >= 500)
+            .arrange_by_key();
+        state
+            .trace_group
+            .register_trace_named(LARGE_VIEW, large.trace);
+    });
+}
+
+fn main() {
+    let handle = BankApp.start(2);
+    let updates = (0..10)
+        .map(|id| {
+            Update::Upsert(Account {
+                id,
+                balance: id as i64 * 100,
+            })
+        })
+        .collect();
+    handle.update_batch(updates);
+    assert_eq!(handle.query(Query::LargeTotal), None);
+
+    install_large(&handle);
+    assert_eq!(handle.query(Query::LargeTotal), Some(3500));
+    handle.update(Update::Upsert(Account {
+        id: 1,
+        balance: 800,
+    }));
+    handle.update(Update::Delete(9));
+    let large = handle.query(Query::LargeTotal);
+    assert_eq!(large, Some(3400));
+
+    handle.drop_view(LARGE_VIEW);
+    assert_eq!(handle.query(Query::LargeTotal), None);
+    assert_eq!(handle.query(Query::Total), Some(4300));
+
+    let report = handle.shutdown().unwrap();
+    assert!(report.is_clean(), "{report:?}");
+    println!("total balance of the large accounts: {large:?}");
+}
@@ -0,0 +1,206 @@
+use std::sync::Mutex;
+use std::task::{Poll, Waker};
+
+use crate::channel::{self, Receiver};
+use crate::command::ClientCommand;
+use crate::internal::SysInternal;
+use crate::{App, Error, Handle, SysTime, WriteToken};
+
+/// A `Handle` which never blocks the calling thread, for use inside async executors.
+///
+/// Waiting on a full channel yields to the executor instead of blocking.
+#[derive(Clone)]
+pub struct AsyncHandle<A: App> {
+    handle: Handle<A>,
+}
+
+impl<A: App> AsyncHandle<A> {
+    pub fn new(handle: Handle<A>) -> Self {
+        AsyncHandle { handle }
+    }
+
+    /// The blocking handle sharing the same app.
+    pub fn handle(&self) -> &Handle<A> {
+        &self.handle
+    }
+
+    /// Resolves to the merged responses of all workers, see `Handle::query`.
+    pub async fn query(&self, query: A::Query) -> Result<A::Response, Error> {
+        self.handle.check()?;
+        let (cmd, pending) = self.handle.query_command(query);
+        self.send(ClientCommand::Query(cmd)).await?;
+        pending.wait_async().await
+    }
+
+    /// Like `query`, but answered at `time`, see `Handle::query_as_of`.
+    pub async fn query_as_of(&self, query: A::Query, time: SysTime) -> Result<A::Response, Error> {
+        self.handle.check()?;
+        let (cmd, pending) = self.handle.query_command(query);
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::QueryAsOf(cmd, time, tx)).await?;
+        self.reply(rx).await??;
+        pending.wait_async().await
+    }
+
+    /// Resolves once the coordinator applied `update`.
+    pub async fn update(&self, update: A::Update) -> Result<WriteToken, Error> {
+        self.handle.check()?;
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::Update(update, tx)).await?;
+        self.reply(rx).await?
+    }
+
+    /// Resolves once the traces of all workers reflect `update`.
+    pub async fn update_sync(&self, update: A::Update) -> Result<WriteToken, Error> {
+        let token = self.update(update).await?;
+        self.wait_for(token).await?;
+        Ok(token)
+    }
+
+    /// Apply all `updates` at the same timestamp, see `Handle::update_batch`.
+    pub async fn update_batch(&self, updates: Vec<A::Update>) -> Result<WriteToken, Error> {
+        self.handle.check()?;
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::UpdateBatch(updates, tx)).await?;
+        self.reply(rx).await?
+    }
+
+    /// Resolves once all traces reflect the write of `token`.
+    pub async fn wait_for(&self, token: WriteToken) -> Result<(), Error> {
+        self.handle.check()?;
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::WaitFor(token, tx)).await?;
+        self.reply(rx).await?
+    }
+
+    /// See `Handle::query_at_least`.
+    pub async fn query_at_least(
+        &self,
+        token: WriteToken,
+        query: A::Query,
+    ) -> Result<A::Response, Error> {
+        self.wait_for(token).await?;
+        self.query(query).await
+    }
+
+    /// Also works after the app failed, `SysInternalCoord::failure` tells why.
+    pub async fn collect_internal_data(&self) -> Result<SysInternal, Error> {
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::CollectInternal(tx)).await?;
+        self.reply(rx).await
+    }
+
+    /// The coordinator drops the sender instead of replying if the app failed.
+    async fn reply<T>(&self, rx: Receiver<T>) -> Result<T, Error> {
+        rx.recv_async()
+            .await
+            .map_err(|_| self.handle.disconnected())
+    }
+
+    /// Waits for the coordinator to make room if the channel is full.
+    async fn send(
+        &self,
+        cmd: ClientCommand<A::Query, A::Update, A::Response>,
+    ) -> Result<(), Error> {
+        let mut cmd = Some(cmd);
+        std::future::poll_fn(|cx| {
+            let mut registered = false;
+            loop {
+                let rest = match self.handle.push(cmd.take().expect("command sent"), false) {
+                    Ok(Some(rest)) => rest,
+                    res => return Poll::Ready(res.map(|_| ())),
+                };
+                cmd = Some(rest);
+                if registered {
+                    return Poll::Pending;
+                }
+                // the coordinator may make room before the waker is registered, try once more
+                self.handle.inner.room.register(cx.waker());
+                registered = true;
+            }
+        })
+        .await
+    }
+}
+
+impl<A: App> From<Handle<A>> for AsyncHandle<A> {
+    fn from(handle: Handle<A>) -> Self {
+        AsyncHandle::new(handle)
+    }
+}
+
+/// Tasks waiting for room in the channels to the coordinator, woken whenever it took commands.
+#[derive(Default)]
+pub(crate) struct Room {
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Room {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake all waiting tasks, some of them may find the channels full again.
+    pub(crate) fn notify(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use super::*;
+    use crate::testing::{block_on, item, Query, TestApp, Update};
+    use crate::AppConfig;
+
+    #[test]
+    fn test_query() {
+        let handle = TestApp.start(2).to_async();
+        let items = block_on(async {
+            handle.update(Update::Put(1, 10)).await?;
+            let token = handle.update_batch(vec![Update::Put(2, 20)]).await?;
+            handle.wait_for(token).await?;
+            handle.query(Query::All).await
+        });
+        assert_eq!(items, Ok(vec![item(1, 10), item(2, 20)]));
+    }
+
+    #[test]
+    fn test_worker_panic() {
+        let handle = TestApp.start(2).to_async();
+        let res = block_on(async {
+            handle.update(Update::Panic(1)).await?;
+            handle.query(Query::Never).await
+        });
+        assert!(matches!(res, Err(Error::WorkerPanicked { index: 1, .. })));
+    }
+
+    #[test]
+    fn test_wait_for_room() {
+        let config = AppConfig::new(1).max_lag(0).client_channel_capacity(1);
+        let handle = TestApp.start_with(config);
+        // the coordinator takes no updates until the worker caught up
+        handle.update(Update::Sleep(0, 200));
+        let (tx, _rx) = channel::unbounded();
+        let filler = ClientCommand::Update(Update::Put(1, 10), tx);
+        assert!(handle.push(filler, false).unwrap().is_none());
+
+        let handle = handle.to_async();
+        let mut update = std::pin::pin!(handle.update(Update::Put(2, 20)));
+        let mut polls = 0;
+        let token = block_on(std::future::poll_fn(|cx| {
+            polls += 1;
+            update.as_mut().poll(cx)
+        }));
+        assert!(token.is_ok());
+        // woken once there is room, instead of spinning
+        assert!(polls < 50, "polled {polls} times");
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Tells the coordinator the current time when timestamps follow a clock, see `AppConfig::clock`.
+pub trait Clock: Send + Sync + 'static {
+    /// Milliseconds since some fixed point, must never go backwards.
+    fn now_millis(&self) -> u64;
+}
+
+/// Milliseconds since the unix epoch.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before unix epoch");
+        now.as_millis() as u64
+    }
+}
+
+/// A clock which only moves when told to, clones share the time.
+///
+/// The app still moves to the new interval on its next tick, `Handle::tick` does it right away.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(millis: u64) -> Self {
+        ManualClock {
+            now: Arc::new(AtomicU64::new(millis)),
+        }
+    }
+
+    /// Panics if `millis` is before the current time.
+    pub fn set(&self, millis: u64) {
+        let prev = self.now.fetch_max(millis, Ordering::SeqCst);
+        assert!(
+            prev <= millis,
+            "clock can not go back from {prev} to {millis}"
+        );
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use timely::communication::Allocator;
+use timely::dataflow::operators::{Exchange, Input, Inspect, Probe};
+use timely::dataflow::{InputHandle, ProbeHandle};
+use timely::worker::Worker;
+use timely::ExchangeData;
+
+use crate::command::{ControlCommand, ServerCommand};
+use crate::query::QueryId;
+use crate::response::ReplyTo;
+use crate::SysTime;
+
+/// The part of `ServerCommand` which can be sent to workers in other processes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum ClusterCommand<Q, U, R> {
+    // the id tells worker 0 where the response goes
+    Query(QueryId, Q, SysTime, u64),
+    Update(U),
+    AdvanceTimestamp(SysTime),
+    Compact(SysTime),
+    Cancel(QueryId),
+    Abort,
+    Shutdown,
+    // sent back to worker 0, `None` if the worker dropped its responder
+    Reply(u64, Option<R>),
+    // sent to worker 0 when the upper of a worker's traces changed
+    Progress(usize, Option<SysTime>),
+    // sent to worker 0 when a worker panicked, with the panic message
+    Panicked(usize, String),
+}
+
+impl<Q, U, R> ClusterCommand<Q, U, R> {
+    /// `None` if the command only makes sense inside the coordinator's process.
+    ///
+    /// Queries carry an in-process sender, they are forwarded with `ServerCommand::ForwardQuery`.
+    pub(crate) fn from_server_command(cmd: ServerCommand<Q, U, R>) -> Option<Self> {
+        let cmd = match cmd {
+            ServerCommand::Update(u) => ClusterCommand::Update(u),
+            ServerCommand::ControlCommand(cmd) => match cmd {
+                ControlCommand::AdvanceTimestamp(time) => ClusterCommand::AdvanceTimestamp(time),
+                ControlCommand::Compact(time) => ClusterCommand::Compact(time),
+                ControlCommand::Cancel(id) => ClusterCommand::Cancel(id),
+                ControlCommand::Abort => ClusterCommand::Abort,
+                ControlCommand::Shutdown => ClusterCommand::Shutdown,
+                ControlCommand::CollectInternal(_)
+                | ControlCommand::Subscribe(..)
+                | ControlCommand::InstallView(..)
+                | ControlCommand::DropView(_) => return None,
+            },
+            ServerCommand::Query(..)
+            | ServerCommand::Forward(..)
+            | ServerCommand::ForwardQuery(..)
+            | ServerCommand::Reply(..)
+            | ServerCommand::Progress(..)
+            | ServerCommand::Panicked(..) => return None,
+        };
+        Some(cmd)
+    }
+}
+
+impl<Q, U, R> From<ClusterCommand<Q, U, R>> for ServerCommand<Q, U, R> {
+    fn from(value: ClusterCommand<Q, U, R>) -> Self {
+        match value {
+            ClusterCommand::Query(id, q, time, reply_id) => {
+                ServerCommand::Query(id, q, time, ReplyTo::Remote(reply_id))
+            }
+            ClusterCommand::Update(u) => ServerCommand::Update(u),
+            ClusterCommand::AdvanceTimestamp(time) => ControlCommand::AdvanceTimestamp(time).into(),
+            ClusterCommand::Compact(time) => ControlCommand::Compact(time).into(),
+            ClusterCommand::Cancel(id) => ControlCommand::Cancel(id).into(),
+            ClusterCommand::Abort => ControlCommand::Abort.into(),
+            ClusterCommand::Shutdown => ControlCommand::Shutdown.into(),
+            ClusterCommand::Reply(id, response) => ServerCommand::Reply(id, response),
+            ClusterCommand::Progress(index, upper) => ServerCommand::Progress(index, upper),
+            ClusterCommand::Panicked(index, message) => ServerCommand::Panicked(index, message),
+        }
+    }
+}
+
+/// Moves commands between the processes, from worker 0 to the others and replies back.
+///
+/// Hides the serialization bounds of `Q`, `U` and `R` from the rest of the worker.
+pub(crate) trait Link<Q, U, R> {
+    fn forward(&mut self, target: usize, cmd: ClusterCommand<Q, U, R>);
+
+    /// Make forwarded commands visible to their targets.
+    fn flush(&mut self);
+
+    /// Commands forwarded to this worker, in the order each sender sent them.
+    fn drain(&mut self) -> Vec<ClusterCommand<Q, U, R>>;
+
+    /// This worker forwards nothing anymore.
+    fn close(&mut self);
+
+    fn is_closed(&self) -> bool;
+
+    /// All workers closed their link and every forwarded command was received.
+    fn done(&self) -> bool;
+}
+
+pub(crate) type LinkFactory<Q, U, R> = fn(&mut Worker<Allocator>) -> Box<dyn Link<Q, U, R>>;
+
+type Envelope<Q, U, R> = (u64, u64, ClusterCommand<Q, U, R>);
+
+struct CommandLink<Q, U, R> {
+    // `None` once closed
+    input: Option<InputHandle<u64, Envelope<Q, U, R>>>,
+    probe: ProbeHandle<u64>,
+    received: Rc<RefCell<Vec<(u64, ClusterCommand<Q, U, R>)>>>,
+    seq: u64,
+    dirty: bool,
+}
+
+/// Build the command dataflow, every worker must call this in the same order as other dataflows.
+pub(crate) fn install_link<Q, U, R>(worker: &mut Worker<Allocator>) -> Box<dyn Link<Q, U, R>>
+where
+    Q: ExchangeData,
+    U: ExchangeData,
+    R: ExchangeData,
+{
+    let received = Rc::new(RefCell::new(vec![]));
+    let buffer = received.clone();
+    let (input, probe) = worker.dataflow_named::<u64, _, _>("CommandLink", |scope| {
+        let (input, stream) = scope.new_input::<Envelope<Q, U, R>>();
+        let probe = stream
+            .exchange(|(target, _, _): &Envelope<Q, U, R>| *target)
+            .inspect(move |(_, seq, cmd): &Envelope<Q, U, R>| {
+                buffer.borrow_mut().push((*seq, cmd.clone()))
+            })
+            .probe();
+        (input, probe)
+    });
+    Box::new(CommandLink {
+        input: Some(input),
+        probe,
+        received,
+        seq: 0,
+        dirty: false,
+    })
+}
+
+impl<Q, U, R> Link<Q, U, R> for CommandLink<Q, U, R>
+where
+    Q: ExchangeData,
+    U: ExchangeData,
+    R: ExchangeData,
+{
+    fn forward(&mut self, target: usize, cmd: ClusterCommand<Q, U, R>) {
+        let input = self.input.as_mut().expect("link already closed");
+        input.send((target as u64, self.seq, cmd));
+        self.seq += 1;
+        self.dirty = true;
+    }
+
+    fn flush(&mut self) {
+        if let Some(input) = self.input.as_mut() {
+            if self.dirty {
+                let next = *input.time() + 1;
+                input.advance_to(next);
+                self.dirty = false;
+            }
+        }
+    }
+
+    fn drain(&mut self) -> Vec<ClusterCommand<Q, U, R>> {
+        let mut received = std::mem::take(&mut *self.received.borrow_mut());
+        // sequence numbers only order the commands of one sender, the sort is stable
+        received.sort_by_key(|(seq, _)| *seq);
+        received.into_iter().map(|(_, cmd)| cmd).collect()
+    }
+
+    fn close(&mut self) {
+        // dropping the input closes it
+        self.input = None;
+    }
+
+    fn is_closed(&self) -> bool {
+        self.input.is_none()
+    }
+
+    fn done(&self) -> bool {
+        self.input.is_none() && self.probe.done()
+    }
+}
@@ -1,38 +1,110 @@
 use crossbeam::channel::Sender;
 
+use crate::channel;
+use crate::cluster::ClusterCommand;
 use crate::internal::{SysInternal, SysInternalWorker};
-use crate::SysTime;
+use crate::query::QueryId;
+use crate::response::ReplyTo;
+use crate::shutdown::ShutdownReport;
+use crate::subscribe::Subscriber;
+use crate::view::ViewBuilder;
+use crate::{Error, SysTime, WriteToken};
 
+/// A query and where the workers send their responses.
 #[derive(Debug)]
-pub enum ClientCommand<Q, U> {
-    Query(Q),
-    Update(U),
-    CollectInternal(Sender<SysInternal>),
+pub struct QueryCommand<Q, R> {
+    pub id: QueryId,
+    pub query: Q,
+    // `Err` if the query is dropped before it reaches the workers
+    pub reply: channel::Sender<Result<R, Error>>,
+}
+
+#[derive(Debug)]
+pub enum ClientCommand<Q, U, R> {
+    Query(QueryCommand<Q, R>),
+    // replies whether the time is readable, the query is only sent if it is
+    QueryAsOf(
+        QueryCommand<Q, R>,
+        SysTime,
+        channel::Sender<Result<(), Error>>,
+    ),
+    // drop the pending peeks of the query
+    Cancel(QueryId),
+    // replies with the timestamp of the update, or why it was rejected
+    Update(U, channel::Sender<Result<WriteToken, Error>>),
+    // all updates are applied at the same timestamp
+    UpdateBatch(Vec<U>, channel::Sender<Result<WriteToken, Error>>),
+    // reply once all traces passed the token, fails if the token is ahead of the frontier
+    WaitFor(WriteToken, channel::Sender<Result<(), Error>>),
+    CollectInternal(channel::Sender<SysInternal>),
+    // move to the clock's current interval right away, replies once the time moved
+    Tick(channel::Sender<()>),
+    // replies the snapshot time, fails in cluster mode
+    Subscribe(Subscriber, channel::Sender<Result<SysTime, Error>>),
+    // replies once the view is sent to all workers
+    InstallView(String, ViewBuilder, channel::Sender<Result<(), Error>>),
+    DropView(String, channel::Sender<Result<(), Error>>),
+    // wait for all workers to exit and report their results
+    Shutdown(Sender<ShutdownReport>),
     DropApp,
 }
 
 #[derive(Debug)]
-pub enum ServerCommand<Q, U> {
-    Query(Q, SysTime),
+pub enum ServerCommand<Q, U, R> {
+    Query(QueryId, Q, SysTime, ReplyTo<R>),
     Update(U),
     ControlCommand(ControlCommand),
+    // forward the command to a worker in another process
+    Forward(usize, ClusterCommand<Q, U, R>),
+    // forward a query to a worker in another process, worker 0 passes its response on
+    ForwardQuery(
+        usize,
+        QueryId,
+        Q,
+        SysTime,
+        channel::Sender<Result<R, Error>>,
+    ),
+    // response of a worker in another process to a forwarded query
+    Reply(u64, Option<R>),
+    // progress of a worker in another process, worker 0 passes it on to the coordinator
+    Progress(usize, Option<SysTime>),
+    // panic of a worker in another process, passed on like `Progress`
+    Panicked(usize, String),
 }
 
 #[derive(Clone, Debug)]
 pub enum ControlCommand {
     AdvanceTimestamp(SysTime),
+    // compact traces up to the time
+    Compact(SysTime),
     CollectInternal(Sender<SysInternalWorker>),
+    // install a subscription with its snapshot time
+    Subscribe(Subscriber, SysTime),
+    InstallView(String, ViewBuilder),
+    DropView(String),
+    // drop the pending peeks of the query
+    Cancel(QueryId),
+    // the app failed, drop all pending peeks
+    Abort,
     Shutdown,
 }
 
-impl<Q, U> From<ControlCommand> for ServerCommand<Q, U> {
-    fn from(value: ControlCommand) -> Self {
-        ServerCommand::ControlCommand(value)
-    }
+/// Sent from workers to the coordinator.
+#[derive(Clone, Debug)]
+pub enum WorkerEvent {
+    Panicked {
+        index: usize,
+        message: String,
+    },
+    // the lowest upper of the worker's traces changed, `None` if no trace holds back
+    Progress {
+        index: usize,
+        upper: Option<SysTime>,
+    },
 }
 
-impl<Q, U> From<(Q, SysTime)> for ServerCommand<Q, U> {
-    fn from((q, t): (Q, SysTime)) -> Self {
-        ServerCommand::Query(q, t)
+impl<Q, U, R> From<ControlCommand> for ServerCommand<Q, U, R> {
+    fn from(value: ControlCommand) -> Self {
+        ServerCommand::ControlCommand(value)
     }
 }
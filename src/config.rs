@@ -0,0 +1,317 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use timely::{CommunicationConfig, Config, WorkerConfig};
+
+use crate::clock::Clock;
+use crate::SysTime;
+
+pub const DEFAULT_MERGE_EFFORT: isize = 1000;
+
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    // worker threads per process
+    pub(crate) workers: usize,
+    pub(crate) cluster: Option<ClusterConfig>,
+    pub(crate) merge_effort: Option<isize>,
+    pub(crate) coord_thread_name: Option<String>,
+    pub(crate) worker_thread_name: Option<String>,
+    pub(crate) worker_stack_size: Option<usize>,
+    pub(crate) client_channel_capacity: Option<usize>,
+    pub(crate) worker_channel_capacity: Option<usize>,
+    pub(crate) backpressure: Backpressure,
+    pub(crate) max_lag: Option<u64>,
+    pub(crate) peek_polling: PeekPolling,
+    pub(crate) compaction: CompactionPolicy,
+    // `None` advances the time after every update
+    pub(crate) clock: Option<ClockConfig>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClusterConfig {
+    /// identity of this process, the coordinator lives in process 0.
+    pub process: usize,
+    /// addresses of all processes, e.g. `127.0.0.1:2101`.
+    pub addresses: Vec<String>,
+    /// verbosely report connection progress.
+    pub report: bool,
+}
+
+/// What handles do when the channel to the coordinator is full.
+///
+/// Only applies to queries and updates, control commands like shutdown have their own channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// wait until the coordinator catches up.
+    Block,
+    /// fail with `Error::WouldBlock`.
+    WouldBlock,
+    /// block updates, but drop the oldest queued query to make room for a new one, the dropped
+    /// query fails with `Error::QueryDropped`.
+    DropOldestQuery,
+}
+
+/// When workers retry pending peeks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PeekPolling {
+    /// once the traces a query read passed its time, and again whenever one of their uppers
+    /// moves, the default.
+    ///
+    /// Queries which read no trace wait on all of them. Peeks pushed outside of
+    /// `App::handle_query` are polled when new, and whenever the upper of any trace moves.
+    Frontier,
+    /// after every step of the dataflows.
+    EveryStep,
+    /// at most once per interval, workers wake up to poll if some peeks are pending.
+    Interval(Duration),
+}
+
+/// How far traces are compacted, queries can only read times not before the compaction frontier.
+///
+/// A tick is one advance of the time, or one interval if the time follows a clock, see
+/// `AppConfig::clock`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompactionPolicy {
+    /// compact up to the latest query time on every tick.
+    Eager,
+    /// compact up to the latest query time once it moved by the given number of ticks.
+    Batched(u64),
+    /// keep the given number of ticks before the latest query time readable.
+    RetainTicks(u64),
+    /// keep every time readable which was the latest query time within the duration.
+    RetainFor(Duration),
+}
+
+impl AppConfig {
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0);
+        AppConfig {
+            workers,
+            cluster: None,
+            merge_effort: Some(DEFAULT_MERGE_EFFORT),
+            coord_thread_name: None,
+            worker_thread_name: None,
+            worker_stack_size: None,
+            client_channel_capacity: None,
+            worker_channel_capacity: None,
+            backpressure: Backpressure::Block,
+            max_lag: None,
+            peek_polling: PeekPolling::Frontier,
+            compaction: CompactionPolicy::Eager,
+            clock: None,
+        }
+    }
+
+    /// Run `workers` threads in each of the processes listed in `addresses`.
+    pub fn cluster(mut self, process: usize, addresses: Vec<String>) -> Self {
+        assert!(
+            process < addresses.len(),
+            "invalid process: {process}, processes: {}",
+            addresses.len()
+        );
+        self.cluster = Some(ClusterConfig {
+            process,
+            addresses,
+            report: false,
+        });
+        self
+    }
+
+    /// Differential's merge effort for idle traces, `None` disables merging while idle.
+    pub fn merge_effort(mut self, effort: Option<isize>) -> Self {
+        self.merge_effort = effort;
+        self
+    }
+
+    /// Name of the coordinator thread, defaults to the app's name.
+    pub fn coord_thread_name(mut self, name: impl Into<String>) -> Self {
+        self.coord_thread_name = Some(name.into());
+        self
+    }
+
+    /// Worker threads are named `{prefix}-{index}`, defaults to `{app name}-worker`.
+    pub fn worker_thread_name(mut self, prefix: impl Into<String>) -> Self {
+        self.worker_thread_name = Some(prefix.into());
+        self
+    }
+
+    pub fn worker_stack_size(mut self, size: usize) -> Self {
+        self.worker_stack_size = Some(size);
+        self
+    }
+
+    /// Bound the channels from handles to the coordinator, see `backpressure` for full channels.
+    pub fn client_channel_capacity(mut self, cap: usize) -> Self {
+        self.client_channel_capacity = Some(cap);
+        self
+    }
+
+    /// Bound the channels from the coordinator to workers, the coordinator blocks when one is full.
+    pub fn worker_channel_capacity(mut self, cap: usize) -> Self {
+        self.worker_channel_capacity = Some(cap);
+        self
+    }
+
+    /// Only matters if the client channel is bounded.
+    pub fn backpressure(mut self, policy: Backpressure) -> Self {
+        self.backpressure = policy;
+        self
+    }
+
+    /// Stop taking updates and queries while the traces of some worker are more than `ticks`
+    /// behind the coordinator's frontier, so full channels push back on producers. Control
+    /// commands like shutdown are still taken, and a failed app is never throttled.
+    ///
+    /// A tick is one advance of the time, or one interval if the time follows a clock.
+    pub fn max_lag(mut self, ticks: u64) -> Self {
+        self.max_lag = Some(ticks);
+        self
+    }
+
+    pub fn peek_polling(mut self, polling: PeekPolling) -> Self {
+        self.peek_polling = polling;
+        self
+    }
+
+    pub fn compaction(mut self, policy: CompactionPolicy) -> Self {
+        self.compaction = policy;
+        self
+    }
+
+    /// Advance the time every `interval` to the clock's milliseconds, rounded down to a multiple
+    /// of the interval, instead of once per update.
+    ///
+    /// All updates received within an interval share its timestamp, queries see them once the
+    /// interval is over, `Handle::query_at_least` waits for that. Ticks in other settings, like
+    /// `max_lag`, are intervals then.
+    pub fn clock(mut self, clock: impl Clock, interval: Duration) -> Self {
+        assert!(interval.as_millis() > 0, "interval must be at least 1ms");
+        self.clock = Some(ClockConfig {
+            clock: Arc::new(clock),
+            interval,
+        });
+        self
+    }
+
+    pub(crate) fn with_default_names(mut self, app: &str) -> Self {
+        self.coord_thread_name
+            .get_or_insert_with(|| app.to_string());
+        self.worker_thread_name
+            .get_or_insert_with(|| format!("{app}-worker"));
+        self
+    }
+
+    /// Worker threads in this process.
+    pub fn local_workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Worker threads in all processes.
+    pub fn workers(&self) -> usize {
+        match &self.cluster {
+            Some(cluster) => self.workers * cluster.addresses.len(),
+            None => self.workers,
+        }
+    }
+
+    pub(crate) fn process(&self) -> usize {
+        self.cluster.as_ref().map(|c| c.process).unwrap_or(0)
+    }
+
+    /// Index of the first worker in this process.
+    pub(crate) fn first_worker(&self) -> usize {
+        self.process() * self.workers
+    }
+
+    pub(crate) fn timely_config(&self) -> Config {
+        let mut config = match &self.cluster {
+            Some(cluster) => Config {
+                communication: CommunicationConfig::Cluster {
+                    threads: self.workers,
+                    process: cluster.process,
+                    addresses: cluster.addresses.clone(),
+                    report: cluster.report,
+                    log_fn: Arc::new(|_| None),
+                },
+                worker: WorkerConfig::default(),
+            },
+            None => Config::process(self.workers),
+        };
+        let dd_config = differential_dataflow::Config {
+            idle_merge_effort: self.merge_effort,
+        };
+        differential_dataflow::configure(&mut config.worker, &dd_config);
+        config
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ClockConfig {
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) interval: Duration,
+}
+
+impl ClockConfig {
+    /// Start of the current interval.
+    pub(crate) fn now(&self) -> SysTime {
+        let interval = self.interval.as_millis() as u64;
+        let now = self.clock.now_millis();
+        SysTime::new(now - now % interval)
+    }
+}
+
+impl fmt::Debug for ClockConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClockConfig")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn sized_channel<T>(
+    capacity: Option<usize>,
+) -> (
+    crossbeam::channel::Sender<T>,
+    crossbeam::channel::Receiver<T>,
+) {
+    match capacity {
+        Some(cap) => crossbeam::channel::bounded(cap),
+        None => crossbeam::channel::unbounded(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_workers() {
+        let addresses = vec!["a:1".to_string(), "b:1".to_string(), "c:1".to_string()];
+        let config = AppConfig::new(2).cluster(1, addresses);
+        assert_eq!(config.local_workers(), 2);
+        assert_eq!(config.workers(), 6);
+        assert_eq!(config.process(), 1);
+        assert_eq!(config.first_worker(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid process")]
+    fn test_cluster_invalid_process() {
+        AppConfig::new(1).cluster(2, vec!["a:1".to_string(), "b:1".to_string()]);
+    }
+
+    #[test]
+    fn test_default_names() {
+        let config = AppConfig::new(1)
+            .worker_thread_name("w")
+            .with_default_names("app");
+        assert_eq!(config.coord_thread_name.as_deref(), Some("app"));
+        assert_eq!(config.worker_thread_name.as_deref(), Some("w"));
+    }
+
+    #[test]
+    fn test_default_peek_polling() {
+        assert_eq!(AppConfig::new(1).peek_polling, PeekPolling::Frontier);
+    }
+}
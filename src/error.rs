@@ -0,0 +1,90 @@
+use std::fmt;
+
+use crate::SysTime;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// the coordinator is gone.
+    Disconnected,
+    /// a worker thread panicked, the app can not make progress anymore.
+    WorkerPanicked { index: usize, message: String },
+    /// the app is shutting down.
+    ShuttingDown,
+    /// the operation did not finish in time.
+    Timeout,
+    /// the channel to the coordinator is full.
+    WouldBlock,
+    /// the traces were compacted past the queried time.
+    TimeCompacted { time: SysTime, since: SysTime },
+    /// the queried time is not readable yet.
+    TimeNotReached { time: SysTime, frontier: SysTime },
+    /// the query was cancelled before all workers responded.
+    Cancelled,
+    /// the query was dropped to make room for a newer one, see `Backpressure::DropOldestQuery`.
+    QueryDropped,
+    /// some worker did not respond to the query.
+    MissingResponse { expected: usize, received: usize },
+    /// a view with the name is already installed.
+    ViewExists { name: String },
+    /// no view with the name is installed.
+    ViewNotFound { name: String },
+    /// the operation can not reach workers in other processes.
+    Unsupported { operation: &'static str },
+    /// `App::route_update` sent an update to a worker which does not exist.
+    InvalidRoute { index: usize, workers: usize },
+    /// nothing of the type is registered under the name.
+    NotRegistered {
+        name: String,
+        type_name: &'static str,
+        registered: Vec<String>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Disconnected => write!(f, "disconnected from coordinator"),
+            Error::WorkerPanicked { index, message } => {
+                write!(f, "worker {index} panicked: {message}")
+            }
+            Error::ShuttingDown => write!(f, "app is shutting down"),
+            Error::Timeout => write!(f, "operation timed out"),
+            Error::WouldBlock => write!(f, "coordinator channel is full"),
+            Error::TimeCompacted { time, since } => {
+                write!(f, "time {time} is compacted, since: {since}")
+            }
+            Error::TimeNotReached { time, frontier } => {
+                write!(f, "time {time} is not reached, frontier: {frontier}")
+            }
+            Error::Cancelled => write!(f, "query cancelled"),
+            Error::QueryDropped => write!(f, "query dropped to make room for a newer one"),
+            Error::MissingResponse { expected, received } => {
+                write!(
+                    f,
+                    "missing responses, expected: {expected}, received: {received}"
+                )
+            }
+            Error::ViewExists { name } => write!(f, "view already installed: {name}"),
+            Error::ViewNotFound { name } => write!(f, "view not found: {name}"),
+            Error::Unsupported { operation } => {
+                write!(f, "{operation} is not supported in cluster mode")
+            }
+            Error::InvalidRoute { index, workers } => {
+                write!(f, "update routed to worker {index}, workers: {workers}")
+            }
+            Error::NotRegistered {
+                name,
+                type_name,
+                registered,
+            } => {
+                let registered = registered.join(", ");
+                write!(
+                    f,
+                    "no {type_name} registered as {name}, registered: [{registered}]"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
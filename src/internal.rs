@@ -1,4 +1,4 @@
-use crate::SysTime;
+use crate::{Error, SysTime};
 
 #[derive(Clone, Debug)]
 pub struct SysInternal {
@@ -11,6 +11,10 @@ pub struct SysInternal {
 pub struct SysInternalCoord {
     pub workers: usize,
     pub frontier: SysTime,
+    /// the earliest time queries can read.
+    pub since: SysTime,
+    /// why the app stopped working, if it did.
+    pub failure: Option<Error>,
 }
 
 #[derive(Clone, Debug)]
@@ -20,6 +24,8 @@ pub struct SysInternalWorker {
     pub upsert_input_info: Vec<SysInternalInput>,
     pub input_info: Vec<SysInternalInput>,
     pub trace_info: Vec<SysInternalTrace>,
+    /// peeks waiting for the traces to catch up.
+    pub pending_peeks: usize,
 }
 
 #[derive(Clone, Debug)]
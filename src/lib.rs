@@ -1,32 +1,72 @@
 #![allow(clippy::type_complexity)]
 #![allow(clippy::new_without_default)]
 
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread::{JoinHandle, Thread};
+use std::time::{Duration, Instant};
 
-use crossbeam::channel::{Receiver, Sender};
-use timely::communication::{Allocate, WorkerGuards};
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::TraceReader;
+use timely::communication::allocator::AllocateBuilder;
+use timely::communication::{Allocate, Allocator};
 use timely::dataflow::Scope;
-use timely::progress::Timestamp;
+use timely::progress::{Antichain, Timestamp};
 use timely::worker::Worker;
-use timely::Config;
+use timely::ExchangeData;
 
-use crate::command::{ClientCommand, ControlCommand, ServerCommand};
+use crate::async_handle::Room;
+use crate::cluster::{install_link, ClusterCommand, Link, LinkFactory};
+use crate::command::{ClientCommand, ControlCommand, QueryCommand, ServerCommand, WorkerEvent};
+use crate::config::{sized_channel, Backpressure, ClockConfig, CompactionPolicy, PeekPolling};
 use crate::internal::{
     SysInternal, SysInternalCoord, SysInternalInput, SysInternalTrace, SysInternalWorker,
 };
+use crate::response::{Outbox, ReplyTo};
+use crate::shutdown::{ShutdownReport, WorkerReport};
+use crate::subscribe::{subscriber, Subscription, SubscriptionTask};
 use crate::timely_util::dd_input::DDInputGroup;
 use crate::timely_util::trace_group::TraceGroup;
 use crate::timely_util::upsert_input::UpsertInputGroup;
+use crate::view::{InstalledView, ViewBuilder, ViewScope};
 
+pub mod async_handle;
+pub mod channel;
+pub mod clock;
+mod cluster;
 mod command;
+pub mod config;
+pub mod error;
 pub mod internal;
+pub mod query;
+pub mod response;
+pub mod runtime;
+pub mod shutdown;
+pub mod subscribe;
+#[cfg(test)]
+mod testing;
 pub mod timely_util;
 pub mod timestamp;
+pub mod view;
 
+pub use async_handle::AsyncHandle;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::{AppConfig, Backpressure, ClusterConfig, CompactionPolicy, PeekPolling};
+pub use error::Error;
+pub use query::{CancelHandle, PendingQuery, QueryId};
+pub use response::Responder;
+pub use runtime::{AppHandle, Runtime, RuntimeBuilder};
 pub use timestamp::SysTime;
 
 pub type SysDiff = i64;
 
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub enum PeekResult {
     NotReady,
     Done,
@@ -34,6 +74,26 @@ pub enum PeekResult {
 
 pub type PeekTask = Box<dyn FnMut() -> PeekResult>;
 
+/// Where the coordinator sends an update.
+pub enum Route<U> {
+    /// send the update to the worker with the given index.
+    Worker(usize, U),
+    /// send every part to its own worker, all parts are applied at the same timestamp.
+    Split(Vec<(usize, U)>),
+    /// send every worker the update built for its index, see `Route::broadcast`.
+    Broadcast(Box<dyn FnMut(usize) -> U>),
+}
+
+impl<U> Route<U> {
+    /// Send a copy of `update` to every worker.
+    pub fn broadcast(update: U) -> Self
+    where
+        U: Clone + 'static,
+    {
+        Route::Broadcast(Box::new(move |_| update.clone()))
+    }
+}
+
 pub struct WorkerContext<'w, A: Allocate> {
     // trace
     pub trace_group: TraceGroup<SysTime>,
@@ -42,12 +102,55 @@ pub struct WorkerContext<'w, A: Allocate> {
     pub input_group: DDInputGroup<SysTime, SysDiff>,
     // peaks
     pub peeks: Vec<PeekTask>,
+    // peeks of each query by the time they read, dropped when the query is cancelled or a view
+    // they read is dropped
+    query_peeks: BTreeMap<SysTime, Vec<QueryPeeks>>,
+    // the upper of each trace when peeks were last handled, see `PeekPolling::Frontier`
+    polled_uppers: BTreeMap<String, Antichain<SysTime>>,
+    // peeks pushed outside of queries when they were last handled, more means some are new
+    polled_peeks: usize,
+    // like peeks, but never done until the subscription is dropped
+    subscriptions: Vec<SubscriptionTask>,
     pub frontier: SysTime,
+    // traces are compacted up to this time
+    since: SysTime,
+    // dataflows installed at runtime
+    views: HashMap<String, InstalledView>,
     pub worker: &'w mut Worker<A>,
     pub shutdown: bool,
+    pub peek_polling: PeekPolling,
+    last_peek_poll: Instant,
+    // last upper sent to the coordinator
+    reported_upper: Option<SysTime>,
+}
+
+/// The peeks a query pushed, and the traces it read while doing so.
+struct QueryPeeks {
+    id: QueryId,
+    traces: BTreeSet<String>,
+    tasks: Vec<PeekTask>,
+    // not handled since the query pushed them
+    new: bool,
 }
 
-pub struct WorkerState<'a> {
+impl QueryPeeks {
+    /// A query which read no trace waits on all of them.
+    fn reads(&self, name: &str) -> bool {
+        self.traces.is_empty() || self.traces.contains(name)
+    }
+}
+
+/// The timestamp an update was applied at, queries at or after it observe the update.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WriteToken(SysTime);
+
+impl WriteToken {
+    pub fn time(&self) -> SysTime {
+        self.0
+    }
+}
+
+pub struct WorkerState<'a, S = ()> {
     // trace
     pub trace_group: &'a mut TraceGroup<SysTime>,
     // input, all input's time should be equal
@@ -56,6 +159,22 @@ pub struct WorkerState<'a> {
     // peaks
     pub peeks: &'a mut Vec<PeekTask>,
     pub frontier: &'a SysTime,
+    // the app's state on this worker, returned by `App::dataflow`
+    pub app: &'a mut S,
+}
+
+impl<'a, S> WorkerState<'a, S> {
+    /// The same state with the app state `f` picks from this one's.
+    pub(crate) fn map_app<T>(self, f: impl FnOnce(&'a mut S) -> &'a mut T) -> WorkerState<'a, T> {
+        WorkerState {
+            trace_group: self.trace_group,
+            upsert_input_group: self.upsert_input_group,
+            input_group: self.input_group,
+            peeks: self.peeks,
+            frontier: self.frontier,
+            app: f(self.app),
+        }
+    }
 }
 
 impl<'w, A: Allocate> WorkerContext<'w, A> {
@@ -66,22 +185,36 @@ impl<'w, A: Allocate> WorkerContext<'w, A> {
             input_group: DDInputGroup::new(),
             worker,
             peeks: vec![],
+            query_peeks: BTreeMap::new(),
+            polled_uppers: BTreeMap::new(),
+            polled_peeks: 0,
+            subscriptions: vec![],
             frontier: SysTime::minimum(),
+            since: SysTime::minimum(),
+            views: HashMap::new(),
             shutdown: false,
+            peek_polling: PeekPolling::Frontier,
+            last_peek_poll: Instant::now(),
+            // traces start at the minimum time, just as the coordinator assumes
+            reported_upper: Some(SysTime::minimum()),
         }
     }
 
-    fn state(&mut self) -> WorkerState<'_> {
+    fn state<'s, S>(&'s mut self, app: &'s mut S) -> WorkerState<'s, S> {
         WorkerState {
             trace_group: &mut self.trace_group,
             upsert_input_group: &mut self.upsert_input_group,
             input_group: &mut self.input_group,
             peeks: &mut self.peeks,
             frontier: &self.frontier,
+            app,
         }
     }
 
-    fn worker_and_state(&mut self) -> (&mut Worker<A>, WorkerState<'_>) {
+    fn worker_and_state<'s, S>(
+        &'s mut self,
+        app: &'s mut S,
+    ) -> (&'s mut Worker<A>, WorkerState<'s, S>) {
         let worker = &mut *self.worker;
         let state = WorkerState {
             trace_group: &mut self.trace_group,
@@ -89,31 +222,145 @@ impl<'w, A: Allocate> WorkerContext<'w, A> {
             input_group: &mut self.input_group,
             peeks: &mut self.peeks,
             frontier: &self.frontier,
+            app,
         };
         (worker, state)
     }
 
+    /// Handle peeks if the polling policy allows it.
+    pub fn poll_peeks(&mut self) {
+        if let PeekPolling::Interval(interval) = self.peek_polling {
+            if self.last_peek_poll.elapsed() < interval {
+                return;
+            }
+        }
+        self.last_peek_poll = Instant::now();
+        match self.peek_polling {
+            PeekPolling::Frontier => self.handle_ready_peeks(),
+            PeekPolling::EveryStep | PeekPolling::Interval(_) => self.handle_peeks(),
+        }
+        self.subscriptions
+            .retain_mut(|s| matches!((s.task)(), PeekResult::NotReady));
+    }
+
+    /// Call `report` with the worker's index and the upper of its traces, if the upper changed.
+    fn report_progress(&mut self, report: impl FnOnce(usize, Option<SysTime>)) {
+        let upper = self.trace_group.upper().into_option();
+        if upper != self.reported_upper {
+            self.reported_upper = upper;
+            report(self.worker.index(), upper);
+        }
+    }
+
+    /// How long the worker may park without missing a peek poll.
+    fn park_timeout(&self) -> Option<Duration> {
+        match self.peek_polling {
+            PeekPolling::Interval(interval)
+                if self.pending_peeks() > 0 || !self.subscriptions.is_empty() =>
+            {
+                Some(interval.saturating_sub(self.last_peek_poll.elapsed()))
+            }
+            _ => None,
+        }
+    }
+
     pub fn handle_peeks(&mut self) {
-        let mut new_peeks = vec![];
-        for mut task in std::mem::take(&mut self.peeks) {
-            let res = task();
-            match res {
-                PeekResult::NotReady => new_peeks.push(task),
-                PeekResult::Done => {}
+        poll_tasks(&mut self.peeks);
+        for queries in self.query_peeks.values_mut() {
+            for query in queries {
+                poll_tasks(&mut query.tasks);
+            }
+        }
+        self.prune_query_peeks();
+    }
+
+    /// Handle the peeks of a query once the traces it read passed its time, and again whenever
+    /// the upper of one of those traces moves.
+    ///
+    /// Peeks pushed outside of queries have no time, they are handled when new and whenever the
+    /// upper of any trace moves.
+    fn handle_ready_peeks(&mut self) {
+        let uppers = self.trace_group.uppers();
+        let advanced: BTreeSet<&String> = uppers
+            .iter()
+            .filter(|(name, upper)| self.polled_uppers.get(*name) != Some(*upper))
+            .map(|(name, _)| name)
+            .collect();
+        if !advanced.is_empty() || self.peeks.len() > self.polled_peeks {
+            poll_tasks(&mut self.peeks);
+            self.polled_peeks = self.peeks.len();
+        }
+        for (time, queries) in self.query_peeks.iter_mut() {
+            for query in queries {
+                let woken = query.new || advanced.iter().any(|name| query.reads(name));
+                query.new = false;
+                let ready = uppers
+                    .iter()
+                    .filter(|(name, _)| query.reads(name))
+                    .all(|(_, upper)| !upper.less_equal(time));
+                if woken && ready {
+                    poll_tasks(&mut query.tasks);
+                }
             }
         }
-        self.peeks = new_peeks;
+        self.polled_uppers = uppers;
+        self.prune_query_peeks();
+    }
+
+    fn prune_query_peeks(&mut self) {
+        for queries in self.query_peeks.values_mut() {
+            queries.retain(|query| !query.tasks.is_empty());
+        }
+        self.query_peeks.retain(|_, queries| !queries.is_empty());
+    }
+
+    /// Peeks not done yet.
+    pub fn pending_peeks(&self) -> usize {
+        let query_peeks: usize = self
+            .query_peeks
+            .values()
+            .flatten()
+            .map(|query| query.tasks.len())
+            .sum();
+        self.peeks.len() + query_peeks
+    }
+
+    /// Remember that the peeks pushed while handling a query belong to it.
+    fn track_peeks(&mut self, id: QueryId, time: SysTime) {
+        // the traces the query looked up, see `start_query`
+        let traces = self.trace_group.take_reads();
+        if !self.peeks.is_empty() {
+            let tasks = std::mem::take(&mut self.peeks);
+            let query = QueryPeeks {
+                id,
+                traces,
+                tasks,
+                new: true,
+            };
+            self.query_peeks.entry(time).or_default().push(query);
+        }
+    }
+
+    /// Forget the traces looked up before the query, `track_peeks` tells which it reads.
+    fn start_query(&mut self) {
+        self.trace_group.take_reads();
     }
 
     pub fn handle_control_command(&mut self, cmd: ControlCommand) {
         match cmd {
             ControlCommand::AdvanceTimestamp(time) => {
-                assert_eq!(self.frontier.step_forward(), time);
-                let prev_time = self.frontier;
+                // the time jumps ahead if it follows a clock
+                assert!(self.frontier < time);
                 self.frontier = time;
                 self.upsert_input_group.advance_to(self.frontier);
                 self.input_group.advance_and_flush(self.frontier);
-                self.trace_group.logical_compaction(prev_time);
+            }
+            ControlCommand::Compact(time) => {
+                assert!(time < self.frontier);
+                self.since = time;
+                self.trace_group.logical_compaction(time);
+                self.upsert_input_group.logical_compaction(time);
+                self.input_group.logical_compaction(time);
             }
             ControlCommand::CollectInternal(tx) => {
                 let trace_bundle_info = self.trace_group.collect_info();
@@ -136,7 +383,7 @@ impl<'w, A: Allocate> WorkerContext<'w, A> {
                 let mut upsert_input_info = Vec::with_capacity(upsert_input_bundle_info.len());
                 for bundle in upsert_input_bundle_info {
                     let info = SysInternalInput {
-                        name: bundle.name.to_string(),
+                        name: bundle.name,
                         time: bundle.time,
                     };
                     upsert_input_info.push(info);
@@ -146,7 +393,7 @@ impl<'w, A: Allocate> WorkerContext<'w, A> {
                 let mut input_info = Vec::with_capacity(input_bundle_info.len());
                 for bundle in input_bundle_info {
                     let info = SysInternalInput {
-                        name: bundle.name.to_string(),
+                        name: bundle.name,
                         time: bundle.time,
                     };
                     input_info.push(info);
@@ -158,133 +405,516 @@ impl<'w, A: Allocate> WorkerContext<'w, A> {
                     upsert_input_info,
                     input_info,
                     trace_info,
+                    pending_peeks: self.pending_peeks(),
                 };
                 let _ = tx.send(worker_info);
             }
+            ControlCommand::Subscribe(subscriber, as_of) => {
+                let task = (subscriber.0)(&mut self.trace_group, self.worker.index(), as_of);
+                self.subscriptions.push(task);
+            }
+            ControlCommand::InstallView(..) => {
+                unreachable!("views are installed by the worker loop")
+            }
+            ControlCommand::DropView(name) => {
+                let view = self.views.remove(&name).expect("view not installed");
+                for trace in &view.traces {
+                    self.trace_group.remove(trace);
+                }
+                self.worker.drop_dataflow(view.dataflow);
+                // the removed traces never advance again, callers notice the dropped responders
+                let reads_view =
+                    |traces: &BTreeSet<String>| view.traces.iter().any(|t| traces.contains(t));
+                for queries in self.query_peeks.values_mut() {
+                    queries.retain(|query| !reads_view(&query.traces));
+                }
+                self.prune_query_peeks();
+                let (dropped, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.subscriptions)
+                    .into_iter()
+                    .partition(|s| view.traces.contains(&s.trace));
+                self.subscriptions = kept;
+                for subscription in dropped {
+                    (subscription.fail)(Error::ViewNotFound { name: name.clone() });
+                }
+            }
+            ControlCommand::Cancel(id) => {
+                // callers notice the dropped responders
+                for queries in self.query_peeks.values_mut() {
+                    queries.retain(|query| query.id != id);
+                }
+                self.prune_query_peeks();
+            }
+            ControlCommand::Abort => {
+                // callers notice the dropped senders
+                self.peeks.clear();
+                self.query_peeks.clear();
+                self.subscriptions.clear();
+            }
             ControlCommand::Shutdown => self.shutdown = true,
         }
     }
 }
 
+impl WorkerContext<'_, Allocator> {
+    /// Build the view's dataflow, its traces are compacted like the others from now on.
+    fn install_view(&mut self, name: String, builder: ViewBuilder) {
+        let traces = self.trace_group.names();
+        let dataflow = self.worker.next_dataflow_index();
+        {
+            // views keep no app state
+            let mut no_state = ();
+            let (worker, state) = self.worker_and_state(&mut no_state);
+            worker.dataflow_named::<SysTime, _, _>(&name, |scope| (builder.0)(scope, state));
+        }
+        let traces: Vec<String> = self
+            .trace_group
+            .names()
+            .difference(&traces)
+            .cloned()
+            .collect();
+        // compacting the older traces again does nothing
+        self.trace_group.logical_compaction(self.since);
+        self.views.insert(name, InstalledView { dataflow, traces });
+    }
+}
+
+fn poll_tasks(tasks: &mut Vec<PeekTask>) {
+    let mut pending = vec![];
+    for mut task in std::mem::take(tasks) {
+        let res = task();
+        match res {
+            PeekResult::NotReady => pending.push(task),
+            PeekResult::Done => {}
+        }
+    }
+    *tasks = pending;
+}
+
+/// State shared between the coordinator and all handles.
+#[derive(Clone, Debug)]
+enum AppState {
+    Running,
+    ShuttingDown,
+    Failed(Error),
+}
+
+impl AppState {
+    fn check(&self) -> Result<(), Error> {
+        match self {
+            AppState::Running => Ok(()),
+            AppState::ShuttingDown => Err(Error::ShuttingDown),
+            AppState::Failed(e) => Err(e.clone()),
+        }
+    }
+}
+
 pub struct Coord<A: App> {
+    // workers in all processes
     workers: usize,
     frontier: SysTime,
-    worker_guards: WorkerGuards<()>,
-    worker_txs: Vec<Sender<ServerCommand<A::Query, A::Update>>>,
+    // traces are compacted up to this time
+    since: SysTime,
+    compaction: CompactionPolicy,
+    // when each query time became the latest one, for `CompactionPolicy::RetainFor`
+    query_times: VecDeque<(SysTime, Instant)>,
+    // taken when shutting down
+    worker_guards: Option<WorkerThreads>,
+    worker_threads: Vec<Thread>,
+    // workers in the coordinator's process, they come first in worker index order
+    worker_txs: Vec<Sender<ServerCommand<A::Query, A::Update, A::Response>>>,
+    // reported by the workers, the ones in other processes report through worker 0
+    worker_uppers: Vec<Option<SysTime>>,
+    // `WaitFor` callers, released once all traces passed their time
+    waiters: Vec<(SysTime, channel::Sender<Result<(), Error>>)>,
+    // names of the installed views
+    views: HashSet<String>,
+    max_lag: Option<u64>,
+    // the time follows this clock instead of the updates
+    clock: Option<ClockConfig>,
+    events_rx: Receiver<WorkerEvent>,
+    state: Arc<Mutex<AppState>>,
 }
 
 impl<A: App> Coord<A> {
-    fn advance_input(&mut self) {
-        self.frontier = self.frontier.step_forward();
+    fn advance_input(&mut self) -> Result<(), Error> {
+        let mut time = self.frontier.step_forward();
+        if let Some(clock) = &self.clock {
+            time = time.max(clock.now());
+        }
+        self.advance_to(time)
+    }
+
+    fn advance_to(&mut self, time: SysTime) -> Result<(), Error> {
+        self.frontier = time;
         let cmd = ControlCommand::AdvanceTimestamp(self.frontier);
-        self.broadcast(cmd);
+        self.broadcast(cmd)?;
+        self.compact()
     }
 
-    fn query_time(&self) -> SysTime {
-        self.frontier
-            .step_back()
-            .expect("initial query time not advanced")
+    /// Close the time of the updates just dispatched, unless the time follows a clock.
+    fn close_updates(&mut self) -> Result<(), Error> {
+        match self.clock {
+            Some(_) => Ok(()),
+            None => self.advance_input(),
+        }
     }
 
-    fn send(&self, idx: usize, cmd: ServerCommand<A::Query, A::Update>) {
-        self.worker_txs[idx].send(cmd).unwrap();
-        self.worker_guards.guards()[idx].thread().unpark();
+    /// Move to the clock's current interval, the updates of the last one become visible.
+    fn tick(&mut self) -> Result<(), Error> {
+        let Some(clock) = &self.clock else {
+            return Ok(());
+        };
+        let time = clock.now();
+        if time <= self.frontier || self.failure().is_some() {
+            return Ok(());
+        }
+        self.advance_to(time)
     }
 
-    fn broadcast(&self, cmd: impl Into<ServerCommand<A::Query, A::Update>> + Clone) {
-        for tx in &self.worker_txs {
-            let cmd = cmd.clone().into();
-            tx.send(cmd).unwrap();
+    fn compact(&mut self) -> Result<(), Error> {
+        let latest = self.query_time();
+        let time = match self.compaction {
+            CompactionPolicy::Eager => latest,
+            CompactionPolicy::Batched(ticks) => {
+                if latest.saturating_sub(self.since) < self.span(ticks) {
+                    return Ok(());
+                }
+                latest
+            }
+            CompactionPolicy::RetainTicks(ticks) => latest.saturating_sub(self.span(ticks)),
+            CompactionPolicy::RetainFor(retention) => {
+                self.query_times.push_back((latest, Instant::now()));
+                // a time stays readable until its successor is older than the retention
+                while self.query_times.len() > 1 && self.query_times[1].1.elapsed() > retention {
+                    self.query_times.pop_front();
+                }
+                self.query_times[0].0
+            }
+        };
+        if time <= self.since {
+            return Ok(());
         }
-        for handle in self.worker_guards.guards() {
-            handle.thread().unpark();
+        self.since = time;
+        self.broadcast(ControlCommand::Compact(time))
+    }
+
+    /// The time `ticks` span, a tick is an interval if the time follows a clock.
+    fn span(&self, ticks: u64) -> SysTime {
+        match &self.clock {
+            Some(clock) => {
+                let interval = clock.interval.as_millis() as u64;
+                SysTime::new(ticks.saturating_mul(interval))
+            }
+            None => SysTime::new(ticks),
         }
     }
-}
 
-pub trait App: Clone + Sized + 'static {
-    type Query: Clone + Send + 'static;
-    type Update: Send + 'static;
+    /// Queries can read times in `[since, frontier)`.
+    fn check_as_of(&self, time: SysTime) -> Result<(), Error> {
+        if time < self.since {
+            return Err(Error::TimeCompacted {
+                time,
+                since: self.since,
+            });
+        }
+        if time >= self.frontier {
+            return Err(Error::TimeNotReached {
+                time,
+                frontier: self.frontier,
+            });
+        }
+        Ok(())
+    }
 
-    fn name(&self) -> &str;
+    /// Some worker fell too far behind, stop taking updates and queries until it catches up.
+    ///
+    /// A failed app drops them anyway, its workers may never catch up.
+    fn throttled(&self) -> bool {
+        let Some(max_lag) = self.max_lag else {
+            return false;
+        };
+        if self.failure().is_some() {
+            return false;
+        }
+        match self.upper() {
+            Some(upper) => self.frontier.saturating_sub(upper) > self.span(max_lag),
+            None => false,
+        }
+    }
 
-    fn dataflow<G: Scope<Timestamp = SysTime>>(scope: &mut G, state: WorkerState<'_>);
+    /// The lowest upper of all traces, `None` if no trace holds back.
+    fn upper(&self) -> Option<SysTime> {
+        self.worker_uppers.iter().flatten().min().copied()
+    }
 
-    fn handle_query(query: Self::Query, time: SysTime, state: WorkerState<'_>);
+    fn record_progress(&mut self, index: usize, upper: Option<SysTime>) {
+        self.worker_uppers[index] = upper;
+        self.release_waiters();
+    }
 
-    fn handle_update(update: Self::Update, state: WorkerState<'_>);
+    fn release_waiters(&mut self) {
+        let upper = self.upper();
+        self.waiters.retain(|(time, sender)| {
+            let done = upper.map_or(true, |upper| *time < upper);
+            if done {
+                let _ = sender.send(Ok(()));
+            }
+            !done
+        });
+    }
 
-    fn start(&self, workers: usize) -> Handle<Self> {
-        // client channels
-        let (client_tx, client_rx) = crossbeam::channel::unbounded();
-        let name = self.name();
-        std::thread::Builder::new()
-            .name(name.into())
-            .spawn(move || start_coord::<Self>(workers, client_rx))
-            .unwrap();
+    fn query_time(&self) -> SysTime {
+        self.frontier
+            .step_back()
+            .expect("initial query time not advanced")
+    }
 
-        Handle {
-            inner: Arc::new(HandleInner { tx: client_tx }),
+    /// The workers each of `updates` goes to, fails if `App::route_update` names a worker which
+    /// does not exist.
+    fn route(&self, updates: Vec<A::Update>) -> Result<Vec<(usize, A::Update)>, Error> {
+        let mut ret = Vec::with_capacity(updates.len());
+        for update in updates {
+            match A::route_update(update, self.workers) {
+                Route::Worker(idx, update) => ret.push((idx, update)),
+                Route::Split(parts) => ret.extend(parts),
+                Route::Broadcast(mut build) => {
+                    ret.extend((0..self.workers).map(|idx| (idx, build(idx))))
+                }
+            }
+        }
+        match ret.iter().find(|(idx, _)| *idx >= self.workers) {
+            Some((index, _)) => Err(Error::InvalidRoute {
+                index: *index,
+                workers: self.workers,
+            }),
+            None => Ok(ret),
         }
     }
-}
 
-fn start_coord<A: App>(workers: usize, client_rx: Receiver<ClientCommand<A::Query, A::Update>>) {
-    let mut td_config = Config::process(workers);
-    let dd_config = differential_dataflow::Config {
-        idle_merge_effort: Some(1000),
-    };
-    differential_dataflow::configure(&mut td_config.worker, &dd_config);
+    /// Apply `updates` at the current time, the inner error rejects them before any is sent.
+    fn apply_updates(
+        &mut self,
+        updates: Vec<A::Update>,
+    ) -> Result<Result<WriteToken, Error>, Error> {
+        let routed = match self.route(updates) {
+            Ok(routed) => routed,
+            Err(e) => return Ok(Err(e)),
+        };
+        let time = self.frontier;
+        self.dispatch_updates(routed)?;
+        // advance only once, so queries see either none or all of the updates
+        self.close_updates()?;
+        Ok(Ok(WriteToken(time)))
+    }
 
-    // server channels
-    let mut worker_txs = Vec::with_capacity(workers);
-    let mut worker_rxs = Vec::with_capacity(workers);
-    for _ in 0..workers {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        worker_txs.push(tx);
-        worker_rxs.push(rx);
+    fn dispatch_updates(&self, routed: Vec<(usize, A::Update)>) -> Result<(), Error> {
+        for (idx, update) in routed {
+            self.send(idx, ServerCommand::Update(update))?;
+        }
+        Ok(())
     }
 
-    let worker_guards = run_timely_workers::<A>(td_config, worker_rxs);
+    fn send(
+        &self,
+        idx: usize,
+        cmd: ServerCommand<A::Query, A::Update, A::Response>,
+    ) -> Result<(), Error> {
+        assert!(
+            idx < self.workers,
+            "invalid worker index: {idx}, workers: {}",
+            self.workers
+        );
+        if idx >= self.worker_txs.len() {
+            return match cmd {
+                // worker 0 keeps the sender and passes the response on
+                ServerCommand::Query(id, q, time, ReplyTo::Local(reply)) => {
+                    self.send(0, ServerCommand::ForwardQuery(idx, id, q, time, reply))
+                }
+                cmd => self.forward(idx, cmd),
+            };
+        }
+        self.worker_txs[idx]
+            .send(cmd)
+            .map_err(|_| worker_exited(idx))?;
+        self.worker_threads[idx].unpark();
+        Ok(())
+    }
 
-    let mut coord = Coord::<A> {
-        workers,
-        frontier: SysTime::minimum(),
-        worker_guards,
-        worker_txs,
-    };
+    /// Send `cmd` to a worker in another process through worker 0.
+    fn forward(
+        &self,
+        idx: usize,
+        cmd: ServerCommand<A::Query, A::Update, A::Response>,
+    ) -> Result<(), Error> {
+        match ClusterCommand::from_server_command(cmd) {
+            Some(cmd) => self.send(0, ServerCommand::Forward(idx, cmd)),
+            // only local workers take part
+            None => Ok(()),
+        }
+    }
+
+    /// Send `cmd` to every worker, even if some of them are gone.
+    fn broadcast(
+        &self,
+        cmd: impl Into<ServerCommand<A::Query, A::Update, A::Response>> + Clone,
+    ) -> Result<(), Error> {
+        let mut ret = Ok(());
+        // forward first, worker 0 must not stop before it forwarded a shutdown
+        for idx in self.worker_txs.len()..self.workers {
+            let res = self.forward(idx, cmd.clone().into());
+            if res.is_err() && ret.is_ok() {
+                ret = res;
+            }
+        }
+        for (idx, tx) in self.worker_txs.iter().enumerate() {
+            let cmd = cmd.clone().into();
+            if tx.send(cmd).is_err() && ret.is_ok() {
+                ret = Err(worker_exited(idx));
+            }
+        }
+        for thread in &self.worker_threads {
+            thread.unpark();
+        }
+        ret
+    }
 
-    coord.advance_input();
+    /// Send the query to every worker, each of them sends its response to the query's sender.
+    fn broadcast_query(
+        &self,
+        cmd: QueryCommand<A::Query, A::Response>,
+        time: SysTime,
+    ) -> Result<(), Error> {
+        for idx in 0..self.workers {
+            let reply_to = ReplyTo::Local(cmd.reply.clone());
+            let query = ServerCommand::Query(cmd.id, cmd.query.clone(), time, reply_to);
+            self.send(idx, query)?;
+        }
+        Ok(())
+    }
 
-    loop {
-        let cmd = match client_rx.recv() {
-            Ok(d) => d,
-            Err(_) => unreachable!(), // client channel 在关闭前会发送 Shutdown 命令
-        };
+    /// Handle `cmd`, returns whether the coordinator should keep running.
+    fn handle(&mut self, cmd: ClientCommand<A::Query, A::Update, A::Response>) -> bool {
+        match self.handle_client_command(cmd) {
+            Ok(running) => running,
+            Err(e) => {
+                self.fail(e);
+                true
+            }
+        }
+    }
 
+    fn handle_client_command(
+        &mut self,
+        cmd: ClientCommand<A::Query, A::Update, A::Response>,
+    ) -> Result<bool, Error> {
+        if self.failure().is_some() {
+            match cmd {
+                // dropped, waiting callers notice the dropped senders
+                ClientCommand::Query(_)
+                | ClientCommand::QueryAsOf(..)
+                | ClientCommand::Cancel(_)
+                | ClientCommand::Update(..)
+                | ClientCommand::UpdateBatch(..)
+                | ClientCommand::WaitFor(..)
+                | ClientCommand::Subscribe(..)
+                | ClientCommand::InstallView(..)
+                | ClientCommand::DropView(..) => return Ok(true),
+                _ => {}
+            }
+        }
         match cmd {
-            ClientCommand::Query(q) => {
-                let time = coord.query_time();
-                coord.broadcast((q, time));
+            ClientCommand::Query(cmd) => {
+                let time = self.query_time();
+                self.broadcast_query(cmd, time)?;
+            }
+            ClientCommand::QueryAsOf(cmd, time, sender) => {
+                let res = self.check_as_of(time);
+                if res.is_ok() {
+                    self.broadcast_query(cmd, time)?;
+                }
+                let _ = sender.send(res);
+            }
+            ClientCommand::Cancel(id) => {
+                self.broadcast(ControlCommand::Cancel(id))?;
+            }
+            ClientCommand::Update(update, sender) => {
+                let _ = sender.send(self.apply_updates(vec![update])?);
+            }
+            ClientCommand::UpdateBatch(updates, sender) => {
+                if updates.is_empty() {
+                    let _ = sender.send(Ok(WriteToken(self.query_time())));
+                    return Ok(true);
+                }
+                let _ = sender.send(self.apply_updates(updates)?);
+            }
+            ClientCommand::WaitFor(token, sender) => {
+                // with a clock, the token's time may still take updates
+                if token.time() > self.frontier {
+                    // the token came from another app
+                    let _ = sender.send(Err(Error::TimeNotReached {
+                        time: token.time(),
+                        frontier: self.frontier,
+                    }));
+                    return Ok(true);
+                }
+                self.waiters.push((token.time(), sender));
+                self.release_waiters();
+            }
+            ClientCommand::Subscribe(subscriber, sender) => {
+                // workers in other processes can not be subscribed
+                let res = if self.worker_txs.len() < self.workers {
+                    Err(Error::Unsupported {
+                        operation: "subscribe",
+                    })
+                } else {
+                    let as_of = self.query_time();
+                    self.broadcast(ControlCommand::Subscribe(subscriber, as_of))?;
+                    Ok(as_of)
+                };
+                let _ = sender.send(res);
+            }
+            ClientCommand::InstallView(name, builder, sender) => {
+                let res = if self.worker_txs.len() < self.workers {
+                    Err(Error::Unsupported {
+                        operation: "install_view",
+                    })
+                } else if self.views.contains(&name) {
+                    Err(Error::ViewExists { name })
+                } else {
+                    self.broadcast(ControlCommand::InstallView(name.clone(), builder))?;
+                    self.views.insert(name);
+                    Ok(())
+                };
+                let _ = sender.send(res);
+            }
+            ClientCommand::DropView(name, sender) => {
+                let res = if self.views.remove(&name) {
+                    self.broadcast(ControlCommand::DropView(name))?;
+                    Ok(())
+                } else {
+                    Err(Error::ViewNotFound { name })
+                };
+                let _ = sender.send(res);
             }
-            ClientCommand::Update(update) => {
-                let cmd = ServerCommand::Update(update);
-                // TODO: maybe more accurate idx?
-                coord.send(0, cmd);
-                coord.advance_input();
+            ClientCommand::Tick(sender) => {
+                self.tick()?;
+                let _ = sender.send(());
             }
             ClientCommand::CollectInternal(sender) => {
                 let (tx, rx) = crossbeam::channel::unbounded();
                 let cmd = ControlCommand::CollectInternal(tx);
-                coord.broadcast(cmd);
-                let mut worker_data = Vec::with_capacity(coord.workers);
+                // dead workers just do not answer
+                let _ = self.broadcast(cmd);
+                let mut worker_data = Vec::with_capacity(self.workers);
                 while let Ok(d) = rx.recv() {
                     worker_data.push(d);
                 }
                 worker_data.sort_by_key(|d| d.index);
                 let coord_data = SysInternalCoord {
-                    workers: coord.workers,
-                    frontier: coord.frontier,
+                    workers: self.workers,
+                    frontier: self.frontier,
+                    since: self.since,
+                    failure: self.failure(),
                 };
                 let ret = SysInternal {
                     coord: coord_data,
@@ -292,91 +922,1727 @@ fn start_coord<A: App>(workers: usize, client_rx: Receiver<ClientCommand<A::Quer
                 };
                 let _ = sender.send(ret);
             }
+            ClientCommand::Shutdown(sender) => {
+                *self.state.lock().unwrap() = AppState::ShuttingDown;
+                // workers answer their pending peeks before exiting
+                let _ = self.broadcast(ControlCommand::Shutdown);
+                let guards = self.worker_guards.take().expect("workers already joined");
+                let _ = sender.send(join_workers(guards, 0));
+                return Ok(false);
+            }
             ClientCommand::DropApp => {
-                coord.broadcast(ControlCommand::Shutdown);
-                break;
+                *self.state.lock().unwrap() = AppState::ShuttingDown;
+                let _ = self.broadcast(ControlCommand::Shutdown);
+                return Ok(false);
             }
         }
+        Ok(true)
     }
-}
-
-fn run_timely_workers<A: App>(
-    config: Config,
-    worker_rxs: Vec<Receiver<ServerCommand<A::Query, A::Update>>>,
-) -> WorkerGuards<()> {
-    let workers = worker_rxs.len();
-    assert!(workers > 0);
-    let msg_rxs: Mutex<Vec<_>> = Mutex::new(worker_rxs.into_iter().map(Some).collect());
-
-    timely::execute(config, move |worker| {
-        let rx = msg_rxs.lock().unwrap()[worker.index() % workers]
-            .take()
-            .unwrap();
 
-        let mut ctx = WorkerContext::new(worker);
-        {
-            let (worker, state) = ctx.worker_and_state();
-            worker.dataflow::<SysTime, _, _>(|scope| A::dataflow(scope, state));
+    fn handle_worker_event(&mut self, event: WorkerEvent) {
+        match event {
+            WorkerEvent::Panicked { index, message } => {
+                self.fail(Error::WorkerPanicked { index, message })
+            }
+            WorkerEvent::Progress { index, upper } => self.record_progress(index, upper),
         }
+    }
 
-        while !ctx.shutdown {
-            // do some maintenance
-            ctx.trace_group.physical_compaction();
-
-            ctx.worker.step_or_park(None);
-
-            // handle commands
-            let commands: Vec<_> = rx.try_iter().collect();
-            for cmd in commands {
-                match cmd {
-                    ServerCommand::Query(query, time) => {
-                        let state = ctx.state();
-                        assert_eq!(state.frontier, time.step_forward());
-                        A::handle_query(query, time, state);
-                    }
-                    ServerCommand::Update(update) => {
-                        A::handle_update(update, ctx.state());
-                    }
-                    ServerCommand::ControlCommand(cmd) => ctx.handle_control_command(cmd),
+    /// Mark the app as failed, only the first failure is kept.
+    fn fail(&mut self, err: Error) {
+        // a worker reports its panic before its channel is closed, prefer the panic message
+        let mut err = err;
+        while let Ok(event) = self.events_rx.try_recv() {
+            match event {
+                WorkerEvent::Panicked { index, message } => {
+                    err = Error::WorkerPanicked { index, message };
+                    break;
                 }
+                WorkerEvent::Progress { index, upper } => self.record_progress(index, upper),
+            }
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if let AppState::Failed(_) = *state {
+                return;
             }
-            ctx.handle_peeks();
+            *state = AppState::Failed(err);
+        }
+        // waiters notice the dropped senders
+        self.waiters.clear();
+        // the remaining workers drop their peeks, so no caller waits forever
+        let _ = self.broadcast(ControlCommand::Abort);
+    }
+
+    fn failure(&self) -> Option<Error> {
+        match &*self.state.lock().unwrap() {
+            AppState::Failed(e) => Some(e.clone()),
+            _ => None,
         }
-    })
-    .unwrap()
+    }
 }
 
-#[derive(Clone)]
-pub struct Handle<A: App> {
-    inner: Arc<HandleInner<A>>,
+/// `first` is the index of the first worker in this process.
+fn join_workers(guards: WorkerThreads, first: usize) -> ShutdownReport {
+    let workers = guards
+        .join()
+        .into_iter()
+        .enumerate()
+        .map(|(idx, result)| WorkerReport {
+            index: first + idx,
+            result,
+        })
+        .collect();
+    ShutdownReport { workers }
 }
 
-impl<A: App> Handle<A> {
-    pub fn query(&self, query: A::Query) {
-        let cmd = ClientCommand::Query(query);
-        self.inner.tx.send(cmd).unwrap();
+fn worker_exited(index: usize) -> Error {
+    Error::WorkerPanicked {
+        index,
+        message: "worker exited unexpectedly".to_string(),
     }
+}
+
+pub trait App: Clone + Sized + 'static {
+    type Query: Clone + Send + 'static;
+    type Update: Send + 'static;
+    /// Every worker answers a query with one response.
+    type Response: Send + 'static;
+    /// Kept by every worker, like the refs of the inputs its dataflow allocated.
+    type State: 'static;
+
+    fn name(&self) -> &str;
+
+    /// Build the app's dataflow, the returned state is handed to the handlers as `state.app`.
+    fn dataflow<G: Scope<Timestamp = SysTime>>(
+        scope: &mut G,
+        state: WorkerState<'_>,
+    ) -> Self::State;
+
+    /// Answer `query` through `responder`, possibly from a peek once the traces reached `time`.
+    fn handle_query(
+        query: Self::Query,
+        time: SysTime,
+        state: WorkerState<'_, Self::State>,
+        responder: Responder<Self::Response>,
+    );
+
+    /// Combine the responses of all workers into the answer of a query.
+    fn merge(responses: Vec<Self::Response>) -> Self::Response;
 
-    pub fn update(&self, update: A::Update) {
-        let cmd = ClientCommand::Update(update);
-        self.inner.tx.send(cmd).unwrap();
+    fn handle_update(update: Self::Update, state: WorkerState<'_, Self::State>);
+
+    /// Decide which workers receive `update`, by default all updates go to worker 0.
+    ///
+    /// Updates sent to different workers at the same timestamp are not ordered, so updates
+    /// of the same upsert key should always be routed to the same worker. An index not below
+    /// `workers` rejects the update with `Error::InvalidRoute`.
+    fn route_update(update: Self::Update, workers: usize) -> Route<Self::Update> {
+        let _ = workers;
+        Route::Worker(0, update)
     }
 
-    pub fn collect_internal_data(&self) -> SysInternal {
-        let (tx, rx) = crossbeam::channel::unbounded();
-        let cmd = ClientCommand::CollectInternal(tx);
-        self.inner.tx.send(cmd).unwrap();
-        rx.recv().unwrap()
+    fn start(&self, workers: usize) -> Handle<Self> {
+        self.start_with(AppConfig::new(workers))
+    }
+
+    fn start_with(&self, config: AppConfig) -> Handle<Self> {
+        assert!(
+            config.cluster.is_none(),
+            "use `start_cluster` to run in cluster mode"
+        );
+        start_app(self, config, None, Dataflows::app(self))
+    }
+
+    /// Start the coordinator and the workers of process 0, other processes call `join_cluster`.
+    ///
+    /// Queries, updates and responses are serialized to reach workers in other processes.
+    fn start_cluster(&self, config: AppConfig) -> Handle<Self>
+    where
+        Self::Query: ExchangeData,
+        Self::Update: ExchangeData,
+        Self::Response: ExchangeData,
+    {
+        assert_eq!(config.process(), 0, "the coordinator lives in process 0");
+        let link: LinkFactory<Self::Query, Self::Update, Self::Response> = install_link;
+        start_app(self, config, Some(link), Dataflows::app(self))
+    }
+
+    /// Run the workers of a process other than 0, returns after the coordinator shut them down.
+    fn join_cluster(&self, config: AppConfig) -> ShutdownReport
+    where
+        Self::Query: ExchangeData,
+        Self::Update: ExchangeData,
+        Self::Response: ExchangeData,
+    {
+        assert_ne!(config.process(), 0, "process 0 should call `start_cluster`");
+        let link: LinkFactory<Self::Query, Self::Update, Self::Response> = install_link;
+        let config = config.with_default_names(self.name());
+        // no coordinator listens in this process, workers report progress and panics through
+        // the link to worker 0
+        let (events_tx, _) = crossbeam::channel::unbounded();
+        let dataflows = Dataflows::app(self);
+        let guards = run_timely_workers::<Self>(&config, vec![], events_tx, Some(link), &dataflows);
+        join_workers(guards, config.first_worker())
     }
 }
 
-struct HandleInner<A: App> {
-    tx: Sender<ClientCommand<A::Query, A::Update>>,
+/// Builds a dataflow on every worker when they start, returns the state of its app.
+pub(crate) type DataflowFn = fn(&mut ViewScope<'_>, WorkerState<'_>) -> Box<dyn Any>;
+
+/// `App::dataflow` of `app`, named after it.
+pub(crate) fn app_dataflow<A: App>(app: &A) -> (String, DataflowFn) {
+    let build: DataflowFn =
+        |scope: &mut ViewScope<'_>, state: WorkerState<'_>| Box::new(A::dataflow(scope, state));
+    (app.name().to_string(), build)
 }
 
-impl<A: App> Drop for HandleInner<A> {
-    fn drop(&mut self) {
-        let cmd = ClientCommand::DropApp;
-        self.tx.send(cmd).unwrap();
+/// The dataflows every worker builds when it starts, and how their states make the app's.
+pub(crate) struct Dataflows<S> {
+    pub(crate) builds: Vec<(String, DataflowFn)>,
+    pub(crate) state: fn(Vec<Box<dyn Any>>) -> S,
+}
+
+impl<S> Clone for Dataflows<S> {
+    fn clone(&self) -> Self {
+        Dataflows {
+            builds: self.builds.clone(),
+            state: self.state,
+        }
+    }
+}
+
+impl<S: 'static> Dataflows<S> {
+    /// The dataflow of `app` alone.
+    fn app<A: App<State = S>>(app: &A) -> Self {
+        Dataflows {
+            builds: vec![app_dataflow(app)],
+            state: |mut states| {
+                let state = states.pop().expect("one dataflow");
+                *state.downcast().expect("state of another app")
+            },
+        }
+    }
+}
+
+fn start_app<A: App>(
+    app: &A,
+    config: AppConfig,
+    link: Option<LinkFactory<A::Query, A::Update, A::Response>>,
+    dataflows: Dataflows<A::State>,
+) -> Handle<A> {
+    let config = config.with_default_names(app.name());
+    // client channels, queries have their own, so the oldest one can be dropped
+    let (client_tx, client_rx) = sized_channel(config.client_channel_capacity);
+    let (query_tx, query_rx) = sized_channel(config.client_channel_capacity);
+    // control commands are never held back by backpressure or throttling
+    let (control_tx, control_rx) = crossbeam::channel::unbounded();
+    let state = Arc::new(Mutex::new(AppState::Running));
+    let coord_state = state.clone();
+    let room = Arc::new(Room::default());
+    let coord_room = room.clone();
+    let backpressure = config.backpressure;
+    let workers = config.workers();
+    let coord_query_rx = query_rx.clone();
+    std::thread::Builder::new()
+        .name(config.coord_thread_name.clone().unwrap())
+        .spawn(move || {
+            start_coord::<A>(
+                config,
+                client_rx,
+                coord_query_rx,
+                control_rx,
+                coord_state,
+                coord_room,
+                link,
+                dataflows,
+            )
+        })
+        .unwrap();
+
+    Handle {
+        inner: Arc::new(HandleInner {
+            tx: client_tx,
+            query_tx,
+            query_rx,
+            control_tx,
+            backpressure,
+            workers,
+            next_query_id: AtomicU64::new(0),
+            state,
+            room,
+        }),
+    }
+}
+
+fn start_coord<A: App>(
+    config: AppConfig,
+    client_rx: Receiver<ClientCommand<A::Query, A::Update, A::Response>>,
+    query_rx: Receiver<QueryCommand<A::Query, A::Response>>,
+    control_rx: Receiver<ClientCommand<A::Query, A::Update, A::Response>>,
+    state: Arc<Mutex<AppState>>,
+    room: Arc<Room>,
+    link: Option<LinkFactory<A::Query, A::Update, A::Response>>,
+    dataflows: Dataflows<A::State>,
+) {
+    let workers = config.workers();
+
+    // server channels, only for workers in this process
+    let local_workers = config.local_workers();
+    let mut worker_txs = Vec::with_capacity(local_workers);
+    let mut worker_rxs = Vec::with_capacity(local_workers);
+    for _ in 0..local_workers {
+        let (tx, rx) = sized_channel(config.worker_channel_capacity);
+        worker_txs.push(tx);
+        worker_rxs.push(rx);
+    }
+
+    let (events_tx, events_rx) = crossbeam::channel::unbounded();
+    // keeps the events channel open after all workers exited
+    let _events_tx = events_tx.clone();
+    let worker_guards = run_timely_workers::<A>(&config, worker_rxs, events_tx, link, &dataflows);
+    let worker_threads = worker_guards.threads();
+
+    let mut coord = Coord::<A> {
+        workers,
+        frontier: SysTime::minimum(),
+        since: SysTime::minimum(),
+        compaction: config.compaction,
+        query_times: VecDeque::new(),
+        worker_guards: Some(worker_guards),
+        worker_threads,
+        worker_txs,
+        worker_uppers: vec![Some(SysTime::minimum()); workers],
+        waiters: vec![],
+        views: HashSet::new(),
+        max_lag: config.max_lag,
+        clock: config.clock.clone(),
+        events_rx: events_rx.clone(),
+        state,
+    };
+
+    if let Err(e) = coord.advance_input() {
+        coord.fail(e);
+    }
+
+    let (no_clients, no_queries) = (crossbeam::channel::never(), crossbeam::channel::never());
+    let ticks = match &config.clock {
+        Some(clock) => crossbeam::channel::tick(clock.interval),
+        None => crossbeam::channel::never(),
+    };
+    loop {
+        // hold back new updates and queries, producers block or fail once the channels are full
+        let (clients, queries) = if coord.throttled() {
+            (&no_clients, &no_queries)
+        } else {
+            (&client_rx, &query_rx)
+        };
+        let running = crossbeam::channel::select! {
+            recv(control_rx) -> cmd => {
+                let cmd = match cmd {
+                    Ok(d) => d,
+                    Err(_) => unreachable!(), // control channel 在关闭前会发送 DropApp 命令
+                };
+                let mut running = true;
+                if matches!(cmd, ClientCommand::Shutdown(_) | ClientCommand::DropApp) {
+                    // apply updates sent before the shutdown, even if some worker lags behind
+                    while let Ok(cmd) = client_rx.try_recv() {
+                        running = coord.handle(cmd);
+                    }
+                }
+                if matches!(
+                    cmd,
+                    ClientCommand::Shutdown(_) | ClientCommand::DropApp | ClientCommand::Cancel(_)
+                ) {
+                    // answer queries sent before the shutdown, a cancelled query must be sent
+                    // before its cancellation
+                    while let Ok(query) = query_rx.try_recv() {
+                        running = coord.handle(ClientCommand::Query(query));
+                    }
+                }
+                running && coord.handle(cmd)
+            }
+            recv(clients) -> cmd => match cmd {
+                Ok(cmd) => coord.handle(cmd),
+                // all handles are gone, their `DropApp` waits on the control channel
+                Err(_) => true,
+            },
+            recv(queries) -> query => {
+                // updates sent before the query are visible to it
+                let mut running = true;
+                while running {
+                    match client_rx.try_recv() {
+                        Ok(cmd) => running = coord.handle(cmd),
+                        Err(_) => break,
+                    }
+                }
+                match query {
+                    Ok(query) => running && coord.handle(ClientCommand::Query(query)),
+                    // all handles are gone, their `DropApp` waits on the control channel
+                    Err(_) => running,
+                }
+            }
+            recv(events_rx) -> event => {
+                let event = event.expect("events channel is kept open");
+                coord.handle_worker_event(event);
+                true
+            }
+            recv(ticks) -> _ => {
+                if let Err(e) = coord.tick() {
+                    coord.fail(e);
+                }
+                true
+            }
+        };
+        // some commands were taken, tasks waiting for room try again
+        room.notify();
+        if !running {
+            break;
+        }
+    }
+    // reject new commands before waiting for the workers
+    drop(client_rx);
+    room.notify();
+}
+
+/// Join handles of the worker threads in this process.
+struct WorkerThreads {
+    handles: Vec<JoinHandle<Result<(), String>>>,
+    // network threads in cluster mode, must outlive the workers
+    _others: Box<dyn Any + Send>,
+}
+
+impl WorkerThreads {
+    fn threads(&self) -> Vec<Thread> {
+        self.handles.iter().map(|h| h.thread().clone()).collect()
+    }
+
+    fn join(mut self) -> Vec<Result<(), String>> {
+        std::mem::take(&mut self.handles)
+            .into_iter()
+            .map(|h| h.join().map_err(panic_message).and_then(|r| r))
+            .collect()
+    }
+}
+
+impl Drop for WorkerThreads {
+    fn drop(&mut self) {
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_timely_workers<A: App>(
+    config: &AppConfig,
+    worker_rxs: Vec<Receiver<ServerCommand<A::Query, A::Update, A::Response>>>,
+    events_tx: Sender<WorkerEvent>,
+    link: Option<LinkFactory<A::Query, A::Update, A::Response>>,
+    dataflows: &Dataflows<A::State>,
+) -> WorkerThreads {
+    let td_config = config.timely_config();
+    let (builders, others) = td_config.communication.try_build().unwrap();
+    let prefix = config.worker_thread_name.clone().unwrap();
+    // workers in other processes only receive commands through the link
+    let mut worker_rxs = worker_rxs.into_iter();
+
+    let mut handles = Vec::with_capacity(builders.len());
+    for (idx, builder) in builders.into_iter().enumerate() {
+        let index = config.first_worker() + idx;
+        let mut thread = std::thread::Builder::new().name(format!("{prefix}-{index}"));
+        if let Some(size) = config.worker_stack_size {
+            thread = thread.stack_size(size);
+        }
+        let rx = worker_rxs.next();
+        let events_tx = events_tx.clone();
+        let worker_config = td_config.worker.clone();
+        let peek_polling = config.peek_polling;
+        let dataflows = dataflows.clone();
+        let handle = thread
+            .spawn(move || {
+                let mut worker = Worker::new(worker_config, builder.build());
+                run_timely_worker::<A>(&mut worker, rx, events_tx, link, peek_polling, dataflows)
+            })
+            .unwrap();
+        handles.push(handle);
+    }
+    WorkerThreads {
+        handles,
+        _others: others,
+    }
+}
+
+fn run_timely_worker<A: App>(
+    worker: &mut Worker<Allocator>,
+    rx: Option<Receiver<ServerCommand<A::Query, A::Update, A::Response>>>,
+    events_tx: Sender<WorkerEvent>,
+    link: Option<LinkFactory<A::Query, A::Update, A::Response>>,
+    peek_polling: PeekPolling,
+    dataflows: Dataflows<A::State>,
+) -> Result<(), String> {
+    // the link must be the first dataflow on every worker, it outlives a panic to report it
+    let mut link: Option<Box<dyn Link<A::Query, A::Update, A::Response>>> = link.map(|f| f(worker));
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        run_worker::<A>(
+            &mut *worker,
+            rx.as_ref(),
+            &events_tx,
+            link.as_deref_mut(),
+            peek_polling,
+            dataflows,
+        )
+    }))
+    .map_err(panic_message);
+    if let Err(message) = &res {
+        let index = worker.index();
+        match link.as_deref_mut() {
+            // workers in other processes report through worker 0
+            Some(link) if rx.is_none() => {
+                let message = message.clone();
+                // the worker may be broken beyond reporting
+                let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    report_remote_panic(worker, link, index, message)
+                }));
+            }
+            // report before `rx` is dropped, so the coordinator sees the panic first
+            _ => {
+                let message = message.clone();
+                let _ = events_tx.send(WorkerEvent::Panicked { index, message });
+            }
+        }
+    }
+    drop(rx);
+
+    // dataflows will never complete if some worker is gone
+    for id in worker.installed_dataflows() {
+        worker.drop_dataflow(id);
+    }
+    res
+}
+
+/// Send the panic of a worker in another process to worker 0, which tells the coordinator.
+fn report_remote_panic<Q, U, R>(
+    worker: &mut Worker<Allocator>,
+    link: &mut dyn Link<Q, U, R>,
+    index: usize,
+    message: String,
+) {
+    // shutting down already
+    if link.is_closed() {
+        return;
+    }
+    link.forward(0, ClusterCommand::Panicked(index, message));
+    link.close();
+    // the network threads only get the command once the worker steps
+    worker.step();
+}
+
+fn run_worker<A: App>(
+    worker: &mut Worker<Allocator>,
+    rx: Option<&Receiver<ServerCommand<A::Query, A::Update, A::Response>>>,
+    events_tx: &Sender<WorkerEvent>,
+    mut link: Option<&mut (dyn Link<A::Query, A::Update, A::Response> + 'static)>,
+    peek_polling: PeekPolling,
+    dataflows: Dataflows<A::State>,
+) {
+    let mut ctx = WorkerContext::new(worker);
+    ctx.peek_polling = peek_polling;
+    let mut states = Vec::with_capacity(dataflows.builds.len());
+    for (name, build) in dataflows.builds {
+        // the app state does not exist before its dataflow
+        let mut no_state = ();
+        let (worker, state) = ctx.worker_and_state(&mut no_state);
+        states.push(worker.dataflow_named::<SysTime, _, _>(&name, |scope| build(scope, state)));
+    }
+    let mut app = (dataflows.state)(states);
+    // responses to queries forwarded from worker 0
+    let outbox: Outbox<A::Response> = Rc::default();
+    // senders of queries worker 0 forwarded to other processes
+    let mut remote_replies: HashMap<u64, channel::Sender<Result<A::Response, Error>>> =
+        HashMap::new();
+    let mut next_reply_id = 0;
+
+    loop {
+        // do some maintenance
+        ctx.trace_group.physical_compaction();
+        ctx.upsert_input_group.physical_compaction();
+        ctx.input_group.physical_compaction();
+
+        let timeout = ctx.park_timeout();
+        ctx.worker.step_or_park(timeout);
+
+        // handle commands
+        let mut commands: Vec<_> = rx.map(|rx| rx.try_iter().collect()).unwrap_or_default();
+        if let Some(link) = link.as_mut() {
+            commands.extend(link.drain().into_iter().map(ServerCommand::from));
+        }
+        for cmd in commands {
+            match cmd {
+                ServerCommand::Query(id, query, time, reply_to) => {
+                    ctx.start_query();
+                    let state = ctx.state(&mut app);
+                    assert!(time < *state.frontier);
+                    A::handle_query(query, time, state, Responder::new(reply_to, &outbox));
+                    ctx.track_peeks(id, time);
+                }
+                ServerCommand::Update(update) => {
+                    A::handle_update(update, ctx.state(&mut app));
+                }
+                ServerCommand::ControlCommand(ControlCommand::InstallView(name, builder)) => {
+                    ctx.install_view(name, builder)
+                }
+                ServerCommand::ControlCommand(cmd) => {
+                    if let ControlCommand::Abort = cmd {
+                        // workers in other processes may never reply, callers notice the dropped
+                        // senders
+                        remote_replies.clear();
+                    }
+                    ctx.handle_control_command(cmd)
+                }
+                ServerCommand::Forward(target, cmd) => {
+                    let link = link.as_mut().expect("not running in cluster mode");
+                    link.forward(target, cmd);
+                }
+                ServerCommand::ForwardQuery(target, id, query, time, reply) => {
+                    let link = link.as_mut().expect("not running in cluster mode");
+                    let reply_id = next_reply_id;
+                    next_reply_id += 1;
+                    remote_replies.insert(reply_id, reply);
+                    link.forward(target, ClusterCommand::Query(id, query, time, reply_id));
+                }
+                ServerCommand::Reply(id, response) => {
+                    // the sender is gone if the app failed
+                    if let Some(reply) = remote_replies.remove(&id) {
+                        if let Some(response) = response {
+                            let _ = reply.send(Ok(response));
+                        }
+                    }
+                }
+                ServerCommand::Progress(index, upper) => {
+                    let _ = events_tx.send(WorkerEvent::Progress { index, upper });
+                }
+                ServerCommand::Panicked(index, message) => {
+                    let _ = events_tx.send(WorkerEvent::Panicked { index, message });
+                }
+            }
+        }
+        ctx.poll_peeks();
+        match link.as_mut() {
+            // workers in other processes report through worker 0, until their link is closed
+            Some(link) if rx.is_none() => {
+                if !ctx.shutdown {
+                    ctx.report_progress(|index, upper| {
+                        link.forward(0, ClusterCommand::Progress(index, upper))
+                    });
+                }
+            }
+            _ => ctx.report_progress(|index, upper| {
+                let _ = events_tx.send(WorkerEvent::Progress { index, upper });
+            }),
+        }
+        if let Some(link) = link.as_mut() {
+            for (id, response) in outbox.borrow_mut().drain(..) {
+                link.forward(0, ClusterCommand::Reply(id, response));
+            }
+            link.flush();
+        }
+
+        // keep running until all pending peeks are answered and forwarded commands are delivered
+        if ctx.shutdown && ctx.pending_peeks() == 0 {
+            match link.as_mut() {
+                None => break,
+                Some(link) => {
+                    link.close();
+                    if link.done() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[derive(Clone)]
+pub struct Handle<A: App> {
+    inner: Arc<HandleInner<A>>,
+}
+
+impl<A: App> Handle<A> {
+    /// Answer `query` at the latest time, merging the responses of all workers.
+    pub fn query(&self, query: A::Query) -> A::Response {
+        self.try_query(query).unwrap()
+    }
+
+    pub fn try_query(&self, query: A::Query) -> Result<A::Response, Error> {
+        self.try_start_query(query)?.wait()
+    }
+
+    /// Like `query`, but fail with `Error::Timeout` and cancel the query if it is not answered
+    /// within `timeout`.
+    pub fn query_timeout(&self, query: A::Query, timeout: Duration) -> A::Response {
+        self.try_query_timeout(query, timeout).unwrap()
+    }
+
+    pub fn try_query_timeout(
+        &self,
+        query: A::Query,
+        timeout: Duration,
+    ) -> Result<A::Response, Error> {
+        self.try_start_query(query)?.wait_timeout(timeout)
+    }
+
+    /// Send `query` without waiting for the responses, the query can be cancelled until it is
+    /// answered.
+    pub fn start_query(&self, query: A::Query) -> PendingQuery<A> {
+        self.try_start_query(query).unwrap()
+    }
+
+    pub fn try_start_query(&self, query: A::Query) -> Result<PendingQuery<A>, Error> {
+        let (cmd, pending) = self.query_command(query);
+        self.send(ClientCommand::Query(cmd))?;
+        Ok(pending)
+    }
+
+    /// Wait for `token`, then send `query`, so the answer reflects the token's write even if it
+    /// came from another handle.
+    pub fn query_at_least(&self, token: WriteToken, query: A::Query) -> A::Response {
+        self.try_query_at_least(token, query).unwrap()
+    }
+
+    pub fn try_query_at_least(
+        &self,
+        token: WriteToken,
+        query: A::Query,
+    ) -> Result<A::Response, Error> {
+        self.try_wait_for(token)?;
+        self.try_query(query)
+    }
+
+    /// Answer `query` at `time` instead of the latest time, `time` must not be compacted yet,
+    /// see `CompactionPolicy`.
+    pub fn query_as_of(&self, query: A::Query, time: SysTime) -> A::Response {
+        self.try_query_as_of(query, time).unwrap()
+    }
+
+    pub fn try_query_as_of(&self, query: A::Query, time: SysTime) -> Result<A::Response, Error> {
+        let (cmd, pending) = self.query_command(query);
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::QueryAsOf(cmd, time, tx))?;
+        rx.recv().map_err(|_| self.disconnected())??;
+        pending.wait()
+    }
+
+    /// Give `query` a new id, the returned `PendingQuery` collects its responses.
+    fn query_command(
+        &self,
+        query: A::Query,
+    ) -> (QueryCommand<A::Query, A::Response>, PendingQuery<A>) {
+        let id = QueryId(self.inner.next_query_id.fetch_add(1, Ordering::Relaxed));
+        let (tx, rx) = channel::unbounded();
+        let cmd = QueryCommand {
+            id,
+            query,
+            reply: tx,
+        };
+        (cmd, PendingQuery::new(self.clone(), id, rx))
+    }
+
+    /// Some worker dropped its responder, or the query never reached it.
+    fn missing_response(&self, received: usize) -> Error {
+        // peeks are dropped without answering if the app failed
+        match &*self.inner.state.lock().unwrap() {
+            AppState::Failed(e) => e.clone(),
+            _ => Error::MissingResponse {
+                expected: self.inner.workers,
+                received,
+            },
+        }
+    }
+
+    pub fn update(&self, update: A::Update) -> WriteToken {
+        self.try_update(update).unwrap()
+    }
+
+    /// Like `update`, but return only once the traces of all workers reflect the update.
+    pub fn update_sync(&self, update: A::Update) -> WriteToken {
+        self.try_update_sync(update).unwrap()
+    }
+
+    pub fn try_update_sync(&self, update: A::Update) -> Result<WriteToken, Error> {
+        let token = self.try_update(update)?;
+        self.try_wait_for(token)?;
+        Ok(token)
+    }
+
+    pub fn try_update(&self, update: A::Update) -> Result<WriteToken, Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::Update(update, tx);
+        self.send(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())?
+    }
+
+    /// Apply all `updates` at the same timestamp, queries observe either none or all of them.
+    pub fn update_batch(&self, updates: Vec<A::Update>) -> WriteToken {
+        self.try_update_batch(updates).unwrap()
+    }
+
+    pub fn try_update_batch(&self, updates: Vec<A::Update>) -> Result<WriteToken, Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::UpdateBatch(updates, tx);
+        self.send(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())?
+    }
+
+    /// Block until all traces reflect the write of `token`, a token of another app which is
+    /// ahead of this one fails with `Error::TimeNotReached`.
+    pub fn wait_for(&self, token: WriteToken) {
+        self.try_wait_for(token).unwrap()
+    }
+
+    pub fn try_wait_for(&self, token: WriteToken) -> Result<(), Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::WaitFor(token, tx);
+        self.send(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())?
+    }
+
+    /// Collect updates in `f` and apply them atomically once `f` returns.
+    pub fn transaction<R>(&self, f: impl FnOnce(&mut Transaction<A>) -> R) -> R {
+        self.try_transaction(f).unwrap()
+    }
+
+    pub fn try_transaction<R>(&self, f: impl FnOnce(&mut Transaction<A>) -> R) -> Result<R, Error> {
+        let mut tx = Transaction { updates: vec![] };
+        let ret = f(&mut tx);
+        self.try_update_batch(tx.updates)?;
+        Ok(ret)
+    }
+
+    /// Stream the updates of trace `Tr` whose key and value pass `filter`, starting with a
+    /// snapshot at the latest query time.
+    ///
+    /// Fails with `Error::Unsupported` in cluster mode.
+    pub fn subscribe<Tr, K, V>(
+        &self,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Subscription<(K, V)>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.try_subscribe::<Tr, K, V>(filter).unwrap()
+    }
+
+    pub fn try_subscribe<Tr, K, V>(
+        &self,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Result<Subscription<(K, V)>, Error>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.subscribe_to::<Tr, K, V>(None, filter)
+    }
+
+    /// Like `subscribe`, but for the trace registered as `name`.
+    pub fn subscribe_named<Tr, K, V>(
+        &self,
+        name: impl Into<String>,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Subscription<(K, V)>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.try_subscribe_named::<Tr, K, V>(name, filter).unwrap()
+    }
+
+    pub fn try_subscribe_named<Tr, K, V>(
+        &self,
+        name: impl Into<String>,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Result<Subscription<(K, V)>, Error>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.subscribe_to::<Tr, K, V>(Some(name.into()), filter)
+    }
+
+    fn subscribe_to<Tr, K, V>(
+        &self,
+        name: Option<String>,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Result<Subscription<(K, V)>, Error>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        let (tx, rx) = channel::unbounded();
+        let (reply_tx, reply_rx) = channel::unbounded();
+        let subscriber = subscriber::<Tr, K, V, _>(name, filter, tx);
+        let cmd = ClientCommand::Subscribe(subscriber, reply_tx);
+        self.send(cmd)?;
+        let as_of = reply_rx.recv().map_err(|_| self.disconnected())??;
+        Ok(Subscription::new(
+            rx,
+            self.inner.workers,
+            as_of,
+            self.inner.state.clone(),
+        ))
+    }
+
+    /// Build a new dataflow on every worker, the traces it registers are compacted and can be
+    /// queried like the ones from `App::dataflow`.
+    ///
+    /// The builder can import the registered traces and the shared or arranged inputs, see
+    /// `UpsertInputGroup::alloc_shared_collection` and `DDInputGroup::alloc_shared_collection`.
+    /// Not supported in cluster mode.
+    pub fn install_view(
+        &self,
+        name: impl Into<String>,
+        builder: impl Fn(&mut ViewScope<'_>, WorkerState<'_>) + Send + Sync + 'static,
+    ) {
+        self.try_install_view(name, builder).unwrap()
+    }
+
+    pub fn try_install_view(
+        &self,
+        name: impl Into<String>,
+        builder: impl Fn(&mut ViewScope<'_>, WorkerState<'_>) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::InstallView(name.into(), ViewBuilder(Arc::new(builder)), tx);
+        self.send(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())?
+    }
+
+    /// Drop the view's dataflow and its traces.
+    ///
+    /// Pending queries which read the traces fail with `Error::MissingResponse`, subscriptions to
+    /// them with `Error::ViewNotFound`.
+    pub fn drop_view(&self, name: impl Into<String>) {
+        self.try_drop_view(name).unwrap()
+    }
+
+    pub fn try_drop_view(&self, name: impl Into<String>) -> Result<(), Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::DropView(name.into(), tx);
+        self.send(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())?
+    }
+
+    /// Move the time to the clock's current interval now instead of at the next tick, so the
+    /// updates of the past intervals become visible. Does nothing without a clock.
+    pub fn tick(&self) {
+        self.try_tick().unwrap()
+    }
+
+    pub fn try_tick(&self) -> Result<(), Error> {
+        let (tx, rx) = channel::unbounded();
+        self.send(ClientCommand::Tick(tx))?;
+        rx.recv().map_err(|_| self.disconnected())
+    }
+
+    pub fn collect_internal_data(&self) -> SysInternal {
+        self.try_collect_internal_data().unwrap()
+    }
+
+    /// Also works after the app failed, `SysInternalCoord::failure` tells why.
+    pub fn try_collect_internal_data(&self) -> Result<SysInternal, Error> {
+        let (tx, rx) = channel::unbounded();
+        let cmd = ClientCommand::CollectInternal(tx);
+        self.send_unchecked(cmd)?;
+        rx.recv().map_err(|_| self.disconnected())
+    }
+
+    /// Wait for pending peeks to be answered, then stop all workers and report how they exited.
+    pub fn shutdown(self) -> Result<ShutdownReport, Error> {
+        self.shutdown_timeout(DEFAULT_SHUTDOWN_TIMEOUT)
+    }
+
+    pub fn shutdown_timeout(self, timeout: Duration) -> Result<ShutdownReport, Error> {
+        let (tx, rx) = crossbeam::channel::bounded(1);
+        let cmd = ClientCommand::Shutdown(tx);
+        // a failed app can still be shut down
+        self.send_unchecked(cmd)?;
+        match rx.recv_timeout(timeout) {
+            Ok(report) => Ok(report),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(self.disconnected()),
+        }
+    }
+
+    pub fn to_async(&self) -> AsyncHandle<A> {
+        AsyncHandle::new(self.clone())
+    }
+
+    /// Returns an error if the app failed or is shutting down.
+    pub fn check(&self) -> Result<(), Error> {
+        self.inner.state.lock().unwrap().check()
+    }
+
+    fn send(&self, cmd: ClientCommand<A::Query, A::Update, A::Response>) -> Result<(), Error> {
+        self.check()?;
+        self.send_unchecked(cmd)
+    }
+
+    fn send_unchecked(
+        &self,
+        cmd: ClientCommand<A::Query, A::Update, A::Response>,
+    ) -> Result<(), Error> {
+        let rest = self.push(cmd, true)?;
+        debug_assert!(rest.is_none());
+        Ok(())
+    }
+
+    /// Queue `cmd` for the coordinator, if `block` is false a full channel hands `cmd` back
+    /// where the backpressure policy would wait.
+    fn push(
+        &self,
+        cmd: ClientCommand<A::Query, A::Update, A::Response>,
+        block: bool,
+    ) -> Result<Option<ClientCommand<A::Query, A::Update, A::Response>>, Error> {
+        if let AppState::ShuttingDown = *self.inner.state.lock().unwrap() {
+            return Err(Error::ShuttingDown);
+        }
+        let inner = &self.inner;
+        let mut query = match cmd {
+            ClientCommand::Query(query) => query,
+            cmd @ (ClientCommand::Update(..)
+            | ClientCommand::UpdateBatch(..)
+            | ClientCommand::QueryAsOf(..)) => {
+                let wait = match cmd {
+                    ClientCommand::QueryAsOf(..) => true,
+                    _ => inner.backpressure != Backpressure::WouldBlock,
+                };
+                if wait && block {
+                    inner.tx.send(cmd).map_err(|_| self.disconnected())?;
+                    return Ok(None);
+                }
+                return match inner.tx.try_send(cmd) {
+                    Ok(()) => Ok(None),
+                    Err(TrySendError::Full(cmd)) if wait => Ok(Some(cmd)),
+                    Err(TrySendError::Full(_)) => Err(Error::WouldBlock),
+                    Err(TrySendError::Disconnected(_)) => Err(self.disconnected()),
+                };
+            }
+            // control commands are never held back
+            cmd => {
+                inner
+                    .control_tx
+                    .send(cmd)
+                    .map_err(|_| self.disconnected())?;
+                return Ok(None);
+            }
+        };
+        loop {
+            if block && inner.backpressure == Backpressure::Block {
+                inner
+                    .query_tx
+                    .send(query)
+                    .map_err(|_| self.disconnected())?;
+                return Ok(None);
+            }
+            match inner.query_tx.try_send(query) {
+                Ok(()) => return Ok(None),
+                Err(TrySendError::Full(q)) => match inner.backpressure {
+                    Backpressure::Block => return Ok(Some(ClientCommand::Query(q))),
+                    Backpressure::WouldBlock => return Err(Error::WouldBlock),
+                    Backpressure::DropOldestQuery => {
+                        if let Ok(dropped) = inner.query_rx.try_recv() {
+                            let _ = dropped.reply.send(Err(Error::QueryDropped));
+                        }
+                        query = q;
+                    }
+                },
+                Err(TrySendError::Disconnected(_)) => return Err(self.disconnected()),
+            }
+        }
+    }
+
+    /// The reason why the coordinator is gone.
+    fn disconnected(&self) -> Error {
+        match self.inner.state.lock().unwrap().check() {
+            Ok(()) => Error::Disconnected,
+            Err(e) => e,
+        }
+    }
+}
+
+pub struct Transaction<A: App> {
+    updates: Vec<A::Update>,
+}
+
+impl<A: App> Transaction<A> {
+    pub fn update(&mut self, update: A::Update) {
+        self.updates.push(update);
+    }
+
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+}
+
+struct HandleInner<A: App> {
+    // updates, throttled with the queries
+    tx: Sender<ClientCommand<A::Query, A::Update, A::Response>>,
+    query_tx: Sender<QueryCommand<A::Query, A::Response>>,
+    // lets handles drop the oldest query when the channel is full
+    query_rx: Receiver<QueryCommand<A::Query, A::Response>>,
+    control_tx: Sender<ClientCommand<A::Query, A::Update, A::Response>>,
+    backpressure: Backpressure,
+    // workers in all processes, each answers every query
+    workers: usize,
+    next_query_id: AtomicU64,
+    state: Arc<Mutex<AppState>>,
+    // async handles waiting for room in the channels
+    room: Arc<Room>,
+}
+
+impl<A: App> Drop for HandleInner<A> {
+    fn drop(&mut self) {
+        let cmd = ClientCommand::DropApp;
+        // the coordinator may already be gone
+        let _ = self.control_tx.send(cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use differential_dataflow::operators::arrange::ArrangeByKey;
+
+    use super::*;
+    use crate::testing::{item, local_addresses, Item, ItemTrace, Query, TestApp, Update};
+
+    /// Process 1 runs on another thread, like a process on the same host would.
+    fn start_test_cluster(workers: usize) -> (Handle<TestApp>, JoinHandle<ShutdownReport>) {
+        let addresses = local_addresses(2);
+        let config = AppConfig::new(workers).cluster(1, addresses.clone());
+        let remote = std::thread::spawn(move || TestApp.join_cluster(config));
+        let handle = TestApp.start_cluster(AppConfig::new(workers).cluster(0, addresses));
+        (handle, remote)
+    }
+
+    /// Peeks pending on all workers, once it equals `expected`.
+    fn wait_pending_peeks(handle: &Handle<TestApp>, expected: usize) {
+        for _ in 0..100 {
+            let internal = handle.collect_internal_data();
+            let pending: usize = internal.workers.iter().map(|w| w.pending_peeks).sum();
+            if pending == expected {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("pending peeks never reached {expected}");
+    }
+
+    #[test]
+    fn test_update_batch() {
+        let handle = TestApp.start(2);
+        handle.update_batch(vec![Update::Put(1, 10), Update::Put(2, 20)]);
+        assert_eq!(handle.query(Query::All), vec![item(1, 10), item(2, 20)]);
+
+        // later updates of a batch win
+        handle.update_batch(vec![
+            Update::Put(1, 11),
+            Update::Delete(2),
+            Update::Put(1, 12),
+        ]);
+        assert_eq!(handle.query(Query::All), vec![item(1, 12)]);
+        assert!(handle.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let handle = TestApp.start(2);
+        let token = handle.update(Update::Put(1, 10));
+        let len = handle.transaction(|tx| {
+            tx.update(Update::Put(2, 20));
+            tx.update(Update::Put(3, 30));
+            tx.len()
+        });
+        assert_eq!(len, 2);
+        let items = handle.query(Query::All);
+        assert_eq!(items, vec![item(1, 10), item(2, 20), item(3, 30)]);
+
+        // an empty transaction does not advance the time
+        let empty = handle.update_batch(vec![]);
+        assert!(empty.time() > token.time());
+        assert_eq!(handle.update_batch(vec![]), empty);
+    }
+
+    #[test]
+    fn test_route_update() {
+        let handle = TestApp.start(3);
+        let updates = (0..6).map(|key| Update::Put(key, key as i64)).collect();
+        handle.update_batch(updates);
+        // every worker answers, a key held by several workers would show up more than once
+        let items = handle.query(Query::All);
+        assert_eq!(
+            items,
+            (0..6).map(|key| item(key, key as i64)).collect::<Vec<_>>()
+        );
+        handle.update(Update::Put(4, 40));
+        assert_eq!(handle.query(Query::Get(4)), vec![item(4, 40)]);
+    }
+
+    #[test]
+    fn test_invalid_route() {
+        let handle = TestApp.start(2);
+        let err = handle.try_update(Update::Misroute(1)).unwrap_err();
+        assert_eq!(
+            err,
+            Error::InvalidRoute {
+                index: 2,
+                workers: 2
+            }
+        );
+
+        // none of the batch is applied
+        let updates = vec![Update::Put(1, 10), Update::Misroute(2)];
+        assert!(handle.try_update_batch(updates).is_err());
+        assert!(handle.check().is_ok());
+        handle.update(Update::Put(3, 30));
+        assert_eq!(handle.query(Query::All), vec![item(3, 30)]);
+    }
+
+    #[test]
+    fn test_errors_after_shutdown() {
+        let handle = TestApp.start(1);
+        let other = handle.clone();
+        assert!(other.check().is_ok());
+        handle.shutdown().unwrap();
+        assert_eq!(other.check(), Err(Error::ShuttingDown));
+        assert_eq!(
+            other.try_query(Query::All).unwrap_err(),
+            Error::ShuttingDown
+        );
+        let err = other.try_update(Update::Put(1, 10)).unwrap_err();
+        assert_eq!(err, Error::ShuttingDown);
+        assert_eq!(err.to_string(), "app is shutting down");
+    }
+
+    #[test]
+    fn test_shutdown_answers_pending_queries() {
+        let handle = TestApp.start(2);
+        handle.update(Update::Put(1, 10));
+        let pending = handle.start_query(Query::Get(1));
+        let report = handle.clone().shutdown().unwrap();
+        assert!(report.is_clean());
+        let indexes: Vec<_> = report.workers.iter().map(|w| w.index).collect();
+        assert_eq!(indexes, vec![0, 1]);
+        assert_eq!(pending.wait().unwrap(), vec![item(1, 10)]);
+    }
+
+    #[test]
+    fn test_worker_panic() {
+        let handle = TestApp.start(2);
+        let pending = handle.start_query(Query::Never);
+        handle.update(Update::Panic(0));
+        let err = pending.wait().unwrap_err();
+        let Error::WorkerPanicked { index, message } = &err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!((*index, message.as_str()), (0, "update panicked"));
+
+        // later calls fail the same way
+        assert_eq!(handle.try_query(Query::All).unwrap_err(), err);
+        assert_eq!(handle.collect_internal_data().coord.failure, Some(err));
+        let report = handle.shutdown().unwrap();
+        assert!(report.workers[0].result.is_err());
+        assert!(report.workers[1].result.is_ok());
+    }
+
+    #[test]
+    fn test_cluster() {
+        let (handle, remote) = start_test_cluster(2);
+        let updates = (0..8).map(|key| Update::Put(key, key as i64)).collect();
+        handle.update_batch(updates);
+        let items = handle.query(Query::All);
+        assert_eq!(
+            items,
+            (0..8).map(|key| item(key, key as i64)).collect::<Vec<_>>()
+        );
+        // worker 3 in process 1 holds the key
+        handle.update(Update::Delete(3));
+        assert_eq!(handle.query(Query::Get(3)), vec![]);
+
+        assert!(handle.shutdown().unwrap().is_clean());
+        let report = remote.join().unwrap();
+        assert!(report.is_clean());
+        let indexes: Vec<_> = report.workers.iter().map(|w| w.index).collect();
+        assert_eq!(indexes, vec![2, 3]);
+    }
+
+    // the addresses of the cluster `test_cluster_processes` runs, for the process it spawns
+    const CLUSTER_ADDRESSES: &str = "DDQUERY_TEST_CLUSTER_ADDRESSES";
+
+    /// Process 1 of `test_cluster_processes`, run in a process of its own.
+    #[test]
+    #[ignore = "spawned by test_cluster_processes"]
+    fn cluster_process() {
+        let addresses = std::env::var(CLUSTER_ADDRESSES).expect("spawned without addresses");
+        let addresses = addresses.split(',').map(String::from).collect();
+        let report = TestApp.join_cluster(AppConfig::new(2).cluster(1, addresses));
+        assert!(report.is_clean(), "{report:?}");
+    }
+
+    #[test]
+    fn test_cluster_processes() {
+        // the test binary runs process 1, like the cluster example does
+        let addresses = local_addresses(2);
+        let exe = std::env::current_exe().unwrap();
+        let mut remote = std::process::Command::new(exe)
+            .args(["--exact", "tests::cluster_process", "--ignored"])
+            .env(CLUSTER_ADDRESSES, addresses.join(","))
+            .spawn()
+            .unwrap();
+        let handle = TestApp.start_cluster(AppConfig::new(2).cluster(0, addresses));
+        let updates = (0..8).map(|key| Update::Put(key, key as i64)).collect();
+        handle.update_batch(updates);
+        let items = handle.query(Query::All);
+        assert_eq!(
+            items,
+            (0..8).map(|key| item(key, key as i64)).collect::<Vec<_>>()
+        );
+        handle.update(Update::Delete(3));
+        assert_eq!(handle.query(Query::Get(3)), vec![]);
+
+        assert!(handle.shutdown().unwrap().is_clean());
+        assert!(remote.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_cluster_remote_panic() {
+        let (handle, remote) = start_test_cluster(2);
+        let pending = handle.start_query(Query::Never);
+        handle.update(Update::Panic(3));
+        let err = pending.wait().unwrap_err();
+        let expected = Error::WorkerPanicked {
+            index: 3,
+            message: "update panicked".to_string(),
+        };
+        assert_eq!(err, expected);
+        assert_eq!(handle.try_query(Query::All).unwrap_err(), expected);
+
+        handle.shutdown().unwrap();
+        let report = remote.join().unwrap();
+        assert!(report.workers[0].result.is_ok());
+        assert!(report.workers[1].result.is_err());
+    }
+
+    #[test]
+    fn test_failed_app_is_not_throttled() {
+        // worker 0 never catches up after its panic
+        let handle = TestApp.start_with(AppConfig::new(2).max_lag(0));
+        handle.update(Update::Panic(0));
+        let err = handle.try_query(Query::All).unwrap_err();
+        assert!(matches!(err, Error::WorkerPanicked { index: 0, .. }));
+
+        let internal = handle.try_collect_internal_data().unwrap();
+        assert_eq!(internal.coord.failure, Some(err));
+        let report = handle.shutdown_timeout(Duration::from_secs(10)).unwrap();
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn test_backpressure_would_block() {
+        let config = AppConfig::new(1)
+            .client_channel_capacity(1)
+            .backpressure(Backpressure::WouldBlock);
+        let handle = TestApp.start_with(config);
+        // queries wait for their responses, so the channel is empty again
+        for key in 0..3 {
+            handle.update(Update::Put(key, 1));
+            assert_eq!(handle.query(Query::Get(key)), vec![item(key, 1)]);
+        }
+        assert!(handle.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_backpressure_drop_oldest_query() {
+        let config = AppConfig::new(1)
+            .client_channel_capacity(1)
+            .worker_channel_capacity(1)
+            .backpressure(Backpressure::DropOldestQuery);
+        let handle = TestApp.start_with(config);
+        handle.update(Update::Put(1, 10));
+        // the coordinator blocks on the sleeping worker, so queries queue up behind it
+        handle.update(Update::Sleep(0, 300));
+        let pending: Vec<_> = (0..4).map(|_| handle.start_query(Query::Get(1))).collect();
+        let results: Vec<_> = pending.into_iter().map(|p| p.wait()).collect();
+        assert!(results.contains(&Err(Error::QueryDropped)), "{results:?}");
+        for res in results {
+            match res {
+                Ok(items) => assert_eq!(items, vec![item(1, 10)]),
+                Err(e) => assert_eq!(e, Error::QueryDropped),
+            }
+        }
+        // the app keeps answering
+        assert_eq!(handle.query(Query::Get(1)), vec![item(1, 10)]);
+    }
+
+    #[test]
+    fn test_write_token() {
+        let handle = TestApp.start(2);
+        let t1 = handle.update(Update::Put(1, 10));
+        let t2 = handle.update(Update::Put(1, 11));
+        assert!(t1 < t2);
+        handle.wait_for(t2);
+        let items = handle.query_at_least(t2, Query::Get(1));
+        assert_eq!(items, vec![item(1, 11)]);
+    }
+
+    #[test]
+    fn test_token_of_another_app() {
+        let ahead = TestApp.start(1);
+        let mut token = ahead.update(Update::Put(1, 10));
+        for key in 2..5 {
+            token = ahead.update(Update::Put(key, 10));
+        }
+        let handle = TestApp.start(1);
+        let err = handle.try_wait_for(token).unwrap_err();
+        assert!(matches!(err, Error::TimeNotReached { .. }));
+        // the coordinator keeps running
+        handle.update(Update::Put(1, 20));
+        assert_eq!(handle.query(Query::Get(1)), vec![item(1, 20)]);
+    }
+
+    #[test]
+    fn test_query_as_of() {
+        let config = AppConfig::new(2).compaction(CompactionPolicy::RetainTicks(1));
+        let handle = TestApp.start_with(config);
+        let t1 = handle.update(Update::Put(1, 10)).time();
+        let t2 = handle.update(Update::Put(1, 11)).time();
+        let t3 = handle.update(Update::Put(1, 12)).time();
+        assert_eq!(handle.query_as_of(Query::Get(1), t3), vec![item(1, 12)]);
+        assert_eq!(handle.query_as_of(Query::Get(1), t2), vec![item(1, 11)]);
+
+        let err = handle.try_query_as_of(Query::Get(1), t1).unwrap_err();
+        assert_eq!(
+            err,
+            Error::TimeCompacted {
+                time: t1,
+                since: t2
+            }
+        );
+        let next = t3.step_forward();
+        let err = handle.try_query_as_of(Query::Get(1), next).unwrap_err();
+        assert!(matches!(err, Error::TimeNotReached { .. }));
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let handle = TestApp.start(2);
+        handle.update(Update::Put(1, 10));
+        handle.update(Update::Put(2, 20));
+        let mut sub = handle.subscribe::<ItemTrace, _, _>(|key: &u64, _: &Item| *key != 2);
+        let as_of = sub.upper();
+        let snapshot = sub.next().unwrap();
+        assert_eq!(snapshot, vec![((1, item(1, 10)), as_of, 1)]);
+
+        let token = handle.update(Update::Put(1, 11));
+        handle.update(Update::Put(2, 21));
+        let mut updates = vec![];
+        while sub.upper() <= token.time() {
+            updates.extend(sub.next().unwrap());
+        }
+        let time = token.time();
+        assert_eq!(
+            updates,
+            vec![((1, item(1, 10)), time, -1), ((1, item(1, 11)), time, 1)]
+        );
+        assert!(handle.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_subscribe_not_registered() {
+        let handle = TestApp.start(2);
+        let mut sub =
+            handle.subscribe_named::<ItemTrace, _, _>("missing", |_: &u64, _: &Item| true);
+        let err = sub.next().unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { name, .. } if name == "missing"));
+    }
+
+    #[test]
+    fn test_subscribe_cluster() {
+        let (handle, remote) = start_test_cluster(2);
+        let err = handle
+            .try_subscribe::<ItemTrace, _, _>(|_: &u64, _: &Item| true)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            Error::Unsupported {
+                operation: "subscribe"
+            }
+        );
+        assert!(handle.shutdown().unwrap().is_clean());
+        assert!(remote.join().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_merge_responses() {
+        let handle = TestApp.start(3);
+        assert_eq!(handle.query(Query::All), vec![]);
+        // each worker responds with its own keys
+        let updates = (0..9).rev().map(|key| Update::Put(key, 1)).collect();
+        handle.update_batch(updates);
+        let items = handle.query(Query::All);
+        assert_eq!(items, (0..9).map(|key| item(key, 1)).collect::<Vec<_>>());
+        // workers without the key respond with nothing
+        assert_eq!(handle.query(Query::Get(4)), vec![item(4, 1)]);
+    }
+
+    #[test]
+    fn test_missing_response() {
+        let handle = TestApp.start(2);
+        let err = handle.try_query(Query::Drop).unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingResponse {
+                expected: 2,
+                received: 0
+            }
+        );
+        // the app keeps working
+        handle.update(Update::Put(1, 10));
+        assert_eq!(handle.query(Query::Get(1)), vec![item(1, 10)]);
+    }
+
+    #[test]
+    fn test_query_timeout() {
+        let handle = TestApp.start(2);
+        let timeout = Duration::from_millis(50);
+        let err = handle.try_query_timeout(Query::Never, timeout).unwrap_err();
+        assert_eq!(err, Error::Timeout);
+        // the expired peeks are dropped on every worker
+        wait_pending_peeks(&handle, 0);
+        assert_eq!(handle.query_timeout(Query::All, timeout * 100), vec![]);
+    }
+
+    #[test]
+    fn test_cancel_query() {
+        let handle = TestApp.start(2);
+        let pending = handle.start_query(Query::Never);
+        wait_pending_peeks(&handle, 2);
+        let cancel = pending.cancel_handle();
+        let waiter = std::thread::spawn(move || pending.wait());
+        cancel.cancel();
+        assert!(cancel.is_cancelled());
+        assert_eq!(waiter.join().unwrap().unwrap_err(), Error::Cancelled);
+        wait_pending_peeks(&handle, 0);
+        assert!(handle.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_peek_polling() {
+        let policies = [
+            PeekPolling::EveryStep,
+            PeekPolling::Interval(Duration::from_millis(5)),
+        ];
+        for policy in policies {
+            let handle = TestApp.start_with(AppConfig::new(2).peek_polling(policy));
+            handle.update(Update::Put(1, 10));
+            assert_eq!(handle.query(Query::Get(1)), vec![item(1, 10)]);
+            // nothing moves the upper while the peeks are retried
+            let timeout = Duration::from_secs(10);
+            let items = handle.query_timeout(Query::Slow(3), timeout);
+            assert_eq!(items, vec![item(1, 10)], "{policy:?}");
+            assert!(handle.shutdown().unwrap().is_clean());
+        }
+    }
+
+    #[test]
+    fn test_frontier_peek_polling() {
+        let handle = TestApp.start(2);
+        handle.update(Update::Put(1, 10));
+        assert_eq!(handle.query(Query::Get(1)), vec![item(1, 10)]);
+        let pending = handle.start_query(Query::Slow(3));
+        let waiter = std::thread::spawn(move || pending.wait());
+        // the peeks wait for the upper of the trace they read to move
+        std::thread::sleep(Duration::from_millis(50));
+        wait_pending_peeks(&handle, 2);
+        for value in 0.. {
+            if waiter.is_finished() {
+                break;
+            }
+            assert!(value < 1000, "the peeks were never woken");
+            handle.update(Update::Put(2, value));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // the query reads the time before the updates
+        assert_eq!(waiter.join().unwrap().unwrap(), vec![item(1, 10)]);
+        assert!(handle.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_drop_view_fails_readers() {
+        let handle = TestApp.start(2);
+        handle.update(Update::Put(1, 10));
+        handle.install_view("copy", |scope, state| {
+            let items = state
+                .upsert_input_group
+                .import_arranged::<Item, _>(scope)
+                .unwrap();
+            let copy = items
+                .as_collection(|key, item| (*key, item.clone()))
+                .arrange_by_key();
+            state.trace_group.register_trace_named("copy", copy.trace);
+        });
+        let held = handle.start_query(Query::Hold("copy".to_string()));
+        let other = handle.start_query(Query::Never);
+        let mut sub = handle.subscribe_named::<ItemTrace, _, _>("copy", |_: &u64, _: &Item| true);
+        let snapshot = sub.next().unwrap();
+        assert_eq!(snapshot.len(), 1);
+        wait_pending_peeks(&handle, 4);
+
+        handle.drop_view("copy");
+        let err = held.wait().unwrap_err();
+        assert!(matches!(err, Error::MissingResponse { .. }));
+        let err = sub.next().unwrap_err();
+        let expected = Error::ViewNotFound {
+            name: "copy".to_string(),
+        };
+        assert_eq!(err, expected);
+        // queries on other traces keep waiting
+        wait_pending_peeks(&handle, 2);
+        other.cancel();
+        assert_eq!(other.wait().unwrap_err(), Error::Cancelled);
+    }
+
+    #[test]
+    fn test_clock_interval() {
+        let interval = Duration::from_secs(10);
+        let clock = ManualClock::new(100_000);
+        let handle = TestApp.start_with(AppConfig::new(2).clock(clock.clone(), interval));
+        // updates within an interval share its timestamp
+        let t1 = handle.update(Update::Put(1, 10));
+        let t2 = handle.update_batch(vec![Update::Put(2, 20), Update::Put(3, 30)]);
+        assert_eq!(t1, t2);
+        assert_eq!(t1.time(), SysTime::new(100_000));
+
+        clock.advance(interval);
+        handle.tick();
+        let items = handle.query_at_least(t2, Query::All);
+        assert_eq!(items, vec![item(1, 10), item(2, 20), item(3, 30)]);
+        let t3 = handle.update(Update::Delete(1));
+        assert_eq!(t3.time(), SysTime::new(110_000));
+
+        // the clock did not move, neither does the time
+        handle.tick();
+        assert_eq!(handle.update(Update::Put(4, 40)), t3);
+    }
+
+    #[test]
+    fn test_clock_retain_ticks() {
+        let interval = Duration::from_secs(10);
+        let clock = ManualClock::new(100_000);
+        let config = AppConfig::new(2)
+            .clock(clock.clone(), interval)
+            .compaction(CompactionPolicy::RetainTicks(1));
+        let handle = TestApp.start_with(config);
+        let t1 = handle.update(Update::Put(1, 10)).time();
+        clock.advance(interval);
+        handle.tick();
+        let t2 = handle.update(Update::Put(1, 11)).time();
+        clock.advance(interval);
+        handle.tick();
+
+        // one interval before the latest query time stays readable
+        assert_eq!(handle.query_as_of(Query::Get(1), t2), vec![item(1, 11)]);
+        let err = handle.try_query_as_of(Query::Get(1), t1).unwrap_err();
+        let since = SysTime::new(109_999);
+        assert_eq!(err, Error::TimeCompacted { time: t1, since });
+    }
+
+    #[test]
+    fn test_update_sync() {
+        let handle = TestApp.start(2);
+        // worker 0 lags behind
+        handle.update(Update::Sleep(0, 100));
+        handle.update_sync(Update::Put(2, 20));
+        assert_eq!(handle.query(Query::Unchecked(2)), vec![item(2, 20)]);
+    }
+
+    #[test]
+    fn test_upsert_batches() {
+        let handle = TestApp.start(2);
+        handle.update(Update::PutBatch(vec![(1, 10), (2, 20), (3, 30)]));
+        let items = handle.query(Query::All);
+        assert_eq!(items, vec![item(1, 10), item(2, 20), item(3, 30)]);
+
+        handle.update(Update::DeleteBatch(vec![1, 3, 5]));
+        assert_eq!(handle.query(Query::All), vec![item(2, 20)]);
+
+        // updates of one key within a batch apply in turn
+        let updates = vec![
+            (2, None),
+            (4, Some(40)),
+            (2, Some(21)),
+            (4, None),
+            (6, Some(60)),
+        ];
+        handle.update(Update::Apply(updates));
+        assert_eq!(handle.query(Query::All), vec![item(2, 21), item(6, 60)]);
+    }
+
+    #[test]
+    fn test_trace_names() {
+        let handle = TestApp.start(1);
+        let internal = handle.collect_internal_data();
+        let names: Vec<_> = internal.workers[0]
+            .trace_info
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        // the type-keyed trace is named after its types
+        assert_eq!(names.len(), 1);
+        assert!(names[0].starts_with("Trace["), "{names:?}");
+        // and found by its type
+        assert!(handle.try_subscribe::<ItemTrace, _, _>(|_, _| true).is_ok());
+    }
+
+    #[test]
+    fn test_route_broadcast() {
+        let handle = TestApp.start(2);
+        // each worker gets the update built for its index
+        handle.update(Update::PutEach(7));
+        assert_eq!(handle.query(Query::All), vec![item(0, 7), item(1, 7)]);
     }
 }
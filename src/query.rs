@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::channel::{self, RecvTimeoutError};
+use crate::command::ClientCommand;
+use crate::{App, Error, Handle};
+
+/// Identifies a query, unique within an app.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct QueryId(pub u64);
+
+/// A query sent to the workers, `wait` merges their responses.
+pub struct PendingQuery<A: App> {
+    rx: channel::Receiver<Result<A::Response, Error>>,
+    cancel: CancelHandle<A>,
+}
+
+impl<A: App> PendingQuery<A> {
+    pub(crate) fn new(
+        handle: Handle<A>,
+        id: QueryId,
+        rx: channel::Receiver<Result<A::Response, Error>>,
+    ) -> Self {
+        let cancel = CancelHandle {
+            handle,
+            id,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        PendingQuery { rx, cancel }
+    }
+
+    pub fn id(&self) -> QueryId {
+        self.cancel.id
+    }
+
+    /// Cancels the query from another thread, while this one waits.
+    pub fn cancel_handle(&self) -> CancelHandle<A> {
+        self.cancel.clone()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel()
+    }
+
+    /// Block until every worker responded.
+    pub fn wait(self) -> Result<A::Response, Error> {
+        self.collect(None)
+    }
+
+    /// Like `wait`, but cancel the query if it is not answered within `timeout`.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<A::Response, Error> {
+        self.collect(Some(Instant::now() + timeout))
+    }
+
+    pub fn wait_deadline(self, deadline: Instant) -> Result<A::Response, Error> {
+        self.collect(Some(deadline))
+    }
+
+    /// Like `wait`, but yields to the executor instead of blocking.
+    pub async fn wait_async(self) -> Result<A::Response, Error> {
+        let workers = self.cancel.handle.inner.workers;
+        let mut responses = Vec::with_capacity(workers);
+        while responses.len() < workers {
+            match self.rx.recv_async().await {
+                Ok(response) => responses.push(response?),
+                Err(_) => return Err(self.missing_response(responses.len())),
+            }
+        }
+        Ok(A::merge(responses))
+    }
+
+    fn collect(self, deadline: Option<Instant>) -> Result<A::Response, Error> {
+        let workers = self.cancel.handle.inner.workers;
+        let mut responses = Vec::with_capacity(workers);
+        while responses.len() < workers {
+            let res = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.rx.recv_timeout(timeout)
+                }
+                None => self.rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match res {
+                Ok(response) => responses.push(response?),
+                Err(RecvTimeoutError::Timeout) => {
+                    // the workers drop the peeks of the expired query
+                    self.cancel.cancel();
+                    return Err(Error::Timeout);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(self.missing_response(responses.len()))
+                }
+            }
+        }
+        Ok(A::merge(responses))
+    }
+
+    fn missing_response(&self, received: usize) -> Error {
+        if self.cancel.is_cancelled() {
+            Error::Cancelled
+        } else {
+            self.cancel.handle.missing_response(received)
+        }
+    }
+}
+
+/// Cancels a pending query, its peeks are dropped on every worker.
+#[derive(Clone)]
+pub struct CancelHandle<A: App> {
+    handle: Handle<A>,
+    id: QueryId,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<A: App> CancelHandle<A> {
+    pub fn cancel(&self) {
+        if self.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // nothing to cancel if the app is gone
+        let _ = self.handle.send_unchecked(ClientCommand::Cancel(self.id));
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
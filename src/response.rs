@@ -0,0 +1,65 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{channel, Error};
+
+/// Replies of a worker waiting to be sent to worker 0, `None` if the responder was dropped.
+pub(crate) type Outbox<R> = Rc<RefCell<Vec<(u64, Option<R>)>>>;
+
+/// Where a worker sends its response to a query.
+#[derive(Debug)]
+pub(crate) enum ReplyTo<R> {
+    Local(channel::Sender<Result<R, Error>>),
+    // the query came from worker 0 in the coordinator's process, replies go back with the id
+    Remote(u64),
+}
+
+/// Answers a query on behalf of one worker, every worker answers each query exactly once.
+///
+/// Dropping the responder without responding makes the query fail with
+/// `Error::MissingResponse`.
+pub struct Responder<R> {
+    // called with `None` if the responder is dropped without responding
+    sink: Option<Box<dyn FnOnce(Option<R>)>>,
+}
+
+impl<R: 'static> Responder<R> {
+    pub(crate) fn new(reply_to: ReplyTo<R>, outbox: &Outbox<R>) -> Self {
+        let sink: Box<dyn FnOnce(Option<R>)> = match reply_to {
+            ReplyTo::Local(tx) => Box::new(move |response| {
+                // the caller may have given up, without a response it notices the dropped sender
+                if let Some(response) = response {
+                    let _ = tx.send(Ok(response));
+                }
+            }),
+            // a remote caller needs to be told
+            ReplyTo::Remote(id) => {
+                let outbox = outbox.clone();
+                Box::new(move |response| outbox.borrow_mut().push((id, response)))
+            }
+        };
+        Responder { sink: Some(sink) }
+    }
+
+    /// Send this worker's part of the answer, panics if called twice.
+    pub fn respond(&mut self, response: R) {
+        let sink = self.sink.take().expect("query already answered");
+        sink(Some(response))
+    }
+
+    /// A responder for `S`, its response is converted with `f` before it is sent.
+    pub(crate) fn map<S: 'static>(mut self, f: fn(S) -> R) -> Responder<S> {
+        let sink = self.sink.take().expect("query already answered");
+        Responder {
+            sink: Some(Box::new(move |response: Option<S>| sink(response.map(f)))),
+        }
+    }
+}
+
+impl<R> Drop for Responder<R> {
+    fn drop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink(None);
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::TraceReader;
+use timely::dataflow::Scope;
+
+use crate::internal::SysInternal;
+use crate::shutdown::ShutdownReport;
+use crate::subscribe::Subscription;
+use crate::{
+    app_dataflow, start_app, App, AppConfig, DataflowFn, Dataflows, Error, Handle, Responder,
+    Route, SysDiff, SysTime, Transaction, WorkerState, WriteToken,
+};
+
+/// Hosts several apps on one coordinator and one set of workers.
+///
+/// The apps share the timestamps and each worker's trace and input groups: a hosted app's
+/// dataflow can import the traces registered by the apps added before it, and its queries can
+/// peek the traces of every app. Trace and input names must be distinct between the apps, by
+/// default they are named after their types. Not supported in cluster mode.
+pub struct Runtime {
+    handle: Handle<Hosted>,
+    // the type and the index of each hosted app by name
+    apps: HashMap<String, (TypeId, usize)>,
+}
+
+impl Runtime {
+    pub fn builder(config: AppConfig) -> RuntimeBuilder {
+        RuntimeBuilder::new(config)
+    }
+
+    /// The handle of the app hosted as `name`, panics if it is not an `A`.
+    pub fn handle<A: App>(&self, name: &str) -> AppHandle<A> {
+        self.try_handle(name)
+            .unwrap_or_else(|| panic!("no app of this type is hosted as {name}"))
+    }
+
+    pub fn try_handle<A: App>(&self, name: &str) -> Option<AppHandle<A>> {
+        match self.apps.get(name) {
+            Some((tid, app)) if *tid == TypeId::of::<A>() => Some(AppHandle {
+                handle: self.handle.clone(),
+                app: *app,
+                _app: PhantomData,
+            }),
+            _ => None,
+        }
+    }
+
+    /// See `Handle::tick`.
+    pub fn tick(&self) {
+        self.handle.tick()
+    }
+
+    pub fn try_tick(&self) -> Result<(), Error> {
+        self.handle.try_tick()
+    }
+
+    pub fn collect_internal_data(&self) -> SysInternal {
+        self.handle.collect_internal_data()
+    }
+
+    pub fn try_collect_internal_data(&self) -> Result<SysInternal, Error> {
+        self.handle.try_collect_internal_data()
+    }
+
+    /// Returns an error if the runtime failed or is shutting down.
+    pub fn check(&self) -> Result<(), Error> {
+        self.handle.check()
+    }
+
+    /// Stop all hosted apps, see `Handle::shutdown`.
+    pub fn shutdown(self) -> Result<ShutdownReport, Error> {
+        self.handle.shutdown()
+    }
+
+    pub fn shutdown_timeout(self, timeout: Duration) -> Result<ShutdownReport, Error> {
+        self.handle.shutdown_timeout(timeout)
+    }
+}
+
+pub struct RuntimeBuilder {
+    config: AppConfig,
+    dataflows: Vec<(String, DataflowFn)>,
+    apps: HashMap<String, (TypeId, usize)>,
+}
+
+impl RuntimeBuilder {
+    pub fn new(config: AppConfig) -> Self {
+        RuntimeBuilder {
+            config,
+            dataflows: vec![],
+            apps: HashMap::new(),
+        }
+    }
+
+    /// Host `app` under its name, its dataflow is built after the ones of the apps added before.
+    pub fn app<A: App>(mut self, app: &A) -> Self {
+        let (name, build) = app_dataflow(app);
+        assert!(
+            !self.apps.contains_key(&name),
+            "an app is already hosted as {name}"
+        );
+        let index = self.dataflows.len();
+        self.apps.insert(name.clone(), (TypeId::of::<A>(), index));
+        self.dataflows.push((name, build));
+        self
+    }
+
+    pub fn start(self) -> Runtime {
+        assert!(
+            self.config.cluster.is_none(),
+            "a runtime can not run in cluster mode"
+        );
+        let hosted = Hosted {
+            name: "runtime".to_string(),
+        };
+        let dataflows = Dataflows {
+            builds: self.dataflows,
+            // the states of the hosted apps, in the order they were added
+            state: |states| states,
+        };
+        let handle = start_app(&hosted, self.config, None, dataflows);
+        Runtime {
+            handle,
+            apps: self.apps,
+        }
+    }
+}
+
+/// Sends the queries and updates of one app hosted by a `Runtime`.
+pub struct AppHandle<A: App> {
+    handle: Handle<Hosted>,
+    // index of the app's state on the workers
+    app: usize,
+    _app: PhantomData<fn() -> A>,
+}
+
+impl<A: App> Clone for AppHandle<A> {
+    fn clone(&self) -> Self {
+        AppHandle {
+            handle: self.handle.clone(),
+            app: self.app,
+            _app: PhantomData,
+        }
+    }
+}
+
+impl<A: App> AppHandle<A> {
+    pub fn query(&self, query: A::Query) -> A::Response {
+        self.try_query(query).unwrap()
+    }
+
+    pub fn try_query(&self, query: A::Query) -> Result<A::Response, Error> {
+        let response = self
+            .handle
+            .try_query(HostedQuery::new::<A>(self.app, query))?;
+        Ok(response.downcast::<A>())
+    }
+
+    pub fn query_timeout(&self, query: A::Query, timeout: Duration) -> A::Response {
+        self.try_query_timeout(query, timeout).unwrap()
+    }
+
+    pub fn try_query_timeout(
+        &self,
+        query: A::Query,
+        timeout: Duration,
+    ) -> Result<A::Response, Error> {
+        let query = HostedQuery::new::<A>(self.app, query);
+        let response = self.handle.try_query_timeout(query, timeout)?;
+        Ok(response.downcast::<A>())
+    }
+
+    /// Like `query`, but the answer reflects the write of `token`, which may come from another
+    /// hosted app.
+    pub fn query_at_least(&self, token: WriteToken, query: A::Query) -> A::Response {
+        self.try_query_at_least(token, query).unwrap()
+    }
+
+    pub fn try_query_at_least(
+        &self,
+        token: WriteToken,
+        query: A::Query,
+    ) -> Result<A::Response, Error> {
+        let query = HostedQuery::new::<A>(self.app, query);
+        let response = self.handle.try_query_at_least(token, query)?;
+        Ok(response.downcast::<A>())
+    }
+
+    pub fn query_as_of(&self, query: A::Query, time: SysTime) -> A::Response {
+        self.try_query_as_of(query, time).unwrap()
+    }
+
+    pub fn try_query_as_of(&self, query: A::Query, time: SysTime) -> Result<A::Response, Error> {
+        let query = HostedQuery::new::<A>(self.app, query);
+        let response = self.handle.try_query_as_of(query, time)?;
+        Ok(response.downcast::<A>())
+    }
+
+    pub fn update(&self, update: A::Update) -> WriteToken {
+        self.try_update(update).unwrap()
+    }
+
+    pub fn try_update(&self, update: A::Update) -> Result<WriteToken, Error> {
+        self.handle
+            .try_update(HostedUpdate::new::<A>(self.app, update))
+    }
+
+    /// See `Handle::update_sync`.
+    pub fn update_sync(&self, update: A::Update) -> WriteToken {
+        self.try_update_sync(update).unwrap()
+    }
+
+    pub fn try_update_sync(&self, update: A::Update) -> Result<WriteToken, Error> {
+        self.handle
+            .try_update_sync(HostedUpdate::new::<A>(self.app, update))
+    }
+
+    pub fn update_batch(&self, updates: Vec<A::Update>) -> WriteToken {
+        self.try_update_batch(updates).unwrap()
+    }
+
+    pub fn try_update_batch(&self, updates: Vec<A::Update>) -> Result<WriteToken, Error> {
+        let updates = updates
+            .into_iter()
+            .map(|update| HostedUpdate::new::<A>(self.app, update))
+            .collect();
+        self.handle.try_update_batch(updates)
+    }
+
+    pub fn wait_for(&self, token: WriteToken) {
+        self.try_wait_for(token).unwrap()
+    }
+
+    pub fn try_wait_for(&self, token: WriteToken) -> Result<(), Error> {
+        self.handle.try_wait_for(token)
+    }
+
+    pub fn transaction<R>(&self, f: impl FnOnce(&mut Transaction<A>) -> R) -> R {
+        self.try_transaction(f).unwrap()
+    }
+
+    pub fn try_transaction<R>(&self, f: impl FnOnce(&mut Transaction<A>) -> R) -> Result<R, Error> {
+        let mut tx = Transaction { updates: vec![] };
+        let ret = f(&mut tx);
+        self.try_update_batch(tx.updates)?;
+        Ok(ret)
+    }
+
+    /// See `Handle::subscribe`, any hosted app's trace can be subscribed.
+    pub fn subscribe<Tr, K, V>(
+        &self,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Subscription<(K, V)>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.try_subscribe::<Tr, K, V>(filter).unwrap()
+    }
+
+    pub fn try_subscribe<Tr, K, V>(
+        &self,
+        filter: impl Fn(&K, &V) -> bool + Send + Sync + 'static,
+    ) -> Result<Subscription<(K, V)>, Error>
+    where
+        Tr: TraceReader<Time = SysTime> + Clone + 'static,
+        for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+        for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+        Tr::Diff: TryInto<SysDiff>,
+        K: Ord + Clone + Send + 'static,
+        V: Ord + Clone + Send + 'static,
+    {
+        self.handle.try_subscribe::<Tr, K, V>(filter)
+    }
+
+    /// Returns an error if the runtime failed or is shutting down.
+    pub fn check(&self) -> Result<(), Error> {
+        self.handle.check()
+    }
+}
+
+/// The app the runtime's coordinator and workers run, it dispatches to the hosted apps.
+#[derive(Clone)]
+struct Hosted {
+    name: String,
+}
+
+impl App for Hosted {
+    type Query = HostedQuery;
+    type Update = HostedUpdate;
+    type Response = HostedResponse;
+    // the state of every hosted app, by index
+    type State = Vec<Box<dyn Any>>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(
+        _scope: &mut G,
+        _state: WorkerState<'_>,
+    ) -> Self::State {
+        // the workers build the dataflows of the hosted apps instead
+        vec![]
+    }
+
+    fn handle_query(
+        query: HostedQuery,
+        time: SysTime,
+        state: WorkerState<'_, Self::State>,
+        responder: Responder<HostedResponse>,
+    ) {
+        let state = state.map_app(|states| &mut states[query.app]);
+        (query.handle)(query.query, time, state, responder)
+    }
+
+    fn merge(responses: Vec<HostedResponse>) -> HostedResponse {
+        let merge = responses.first().expect("every worker responds").merge;
+        merge(responses)
+    }
+
+    fn handle_update(update: HostedUpdate, state: WorkerState<'_, Self::State>) {
+        let state = state.map_app(|states| &mut states[update.app]);
+        (update.handle)(update.update, state)
+    }
+
+    fn route_update(update: HostedUpdate, workers: usize) -> Route<HostedUpdate> {
+        (update.route)(update.app, update.update, workers)
+    }
+}
+
+/// A query of some hosted app.
+trait AnyData: Send {
+    fn clone_box(&self) -> Box<dyn AnyData>;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + 'static> AnyData for T {
+    fn clone_box(&self) -> Box<dyn AnyData> {
+        Box::new(self.clone())
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+fn downcast<T: 'static>(data: Box<dyn Any>) -> T {
+    *data.downcast().expect("data of another app")
+}
+
+fn app_state<A: App>(state: &mut Box<dyn Any>) -> &mut A::State {
+    state.downcast_mut().expect("state of another app")
+}
+
+/// A hosted app's query, together with how that app answers it.
+struct HostedQuery {
+    // index of the app's state
+    app: usize,
+    query: Box<dyn AnyData>,
+    handle: fn(Box<dyn AnyData>, SysTime, WorkerState<'_, Box<dyn Any>>, Responder<HostedResponse>),
+}
+
+impl HostedQuery {
+    fn new<A: App>(app: usize, query: A::Query) -> Self {
+        HostedQuery {
+            app,
+            query: Box::new(query),
+            handle: handle_query::<A>,
+        }
+    }
+}
+
+impl Clone for HostedQuery {
+    fn clone(&self) -> Self {
+        HostedQuery {
+            app: self.app,
+            query: self.query.clone_box(),
+            handle: self.handle,
+        }
+    }
+}
+
+fn handle_query<A: App>(
+    query: Box<dyn AnyData>,
+    time: SysTime,
+    state: WorkerState<'_, Box<dyn Any>>,
+    responder: Responder<HostedResponse>,
+) {
+    let responder = responder.map(HostedResponse::new::<A>);
+    let state = state.map_app(app_state::<A>);
+    A::handle_query(downcast(query.into_any()), time, state, responder)
+}
+
+/// A hosted app's update, unlike queries it is only copied if the app broadcasts it.
+struct HostedUpdate {
+    // index of the app's state
+    app: usize,
+    update: Box<dyn Any + Send>,
+    handle: fn(Box<dyn Any + Send>, WorkerState<'_, Box<dyn Any>>),
+    route: fn(usize, Box<dyn Any + Send>, usize) -> Route<HostedUpdate>,
+}
+
+impl HostedUpdate {
+    fn new<A: App>(app: usize, update: A::Update) -> Self {
+        HostedUpdate {
+            app,
+            update: Box::new(update),
+            handle: handle_update::<A>,
+            route: route_update::<A>,
+        }
+    }
+}
+
+fn handle_update<A: App>(update: Box<dyn Any + Send>, state: WorkerState<'_, Box<dyn Any>>) {
+    A::handle_update(downcast(update), state.map_app(app_state::<A>))
+}
+
+fn route_update<A: App>(
+    app: usize,
+    update: Box<dyn Any + Send>,
+    workers: usize,
+) -> Route<HostedUpdate> {
+    match A::route_update(downcast(update), workers) {
+        Route::Worker(idx, update) => Route::Worker(idx, HostedUpdate::new::<A>(app, update)),
+        Route::Split(parts) => Route::Split(
+            parts
+                .into_iter()
+                .map(|(idx, update)| (idx, HostedUpdate::new::<A>(app, update)))
+                .collect(),
+        ),
+        Route::Broadcast(mut build) => {
+            Route::Broadcast(Box::new(move |idx| HostedUpdate::new::<A>(app, build(idx))))
+        }
+    }
+}
+
+struct HostedResponse {
+    response: Box<dyn Any + Send>,
+    merge: fn(Vec<HostedResponse>) -> HostedResponse,
+}
+
+impl HostedResponse {
+    fn new<A: App>(response: A::Response) -> Self {
+        HostedResponse {
+            response: Box::new(response),
+            merge: merge_responses::<A>,
+        }
+    }
+
+    fn downcast<A: App>(self) -> A::Response {
+        *self.response.downcast().expect("response of another app")
+    }
+}
+
+fn merge_responses<A: App>(responses: Vec<HostedResponse>) -> HostedResponse {
+    let responses = responses
+        .into_iter()
+        .map(HostedResponse::downcast::<A>)
+        .collect();
+    HostedResponse::new::<A>(A::merge(responses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{item, read_items, ItemTrace, Query, TestApp, Update};
+    use crate::timely_util::trace_beyond;
+    use crate::PeekResult;
+
+    /// Counts the items of `TestApp`, which it hosts no dataflow for.
+    #[derive(Clone)]
+    struct CountApp;
+
+    impl App for CountApp {
+        type Query = ();
+        type Update = ();
+        type Response = usize;
+        type State = ();
+
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn dataflow<G: Scope<Timestamp = SysTime>>(_scope: &mut G, _state: WorkerState<'_>) {}
+
+        fn handle_query(
+            _query: (),
+            time: SysTime,
+            state: WorkerState<'_>,
+            mut responder: Responder<usize>,
+        ) {
+            let mut trace = state.trace_group.get::<ItemTrace>().unwrap().clone();
+            state.peeks.push(Box::new(move || {
+                if !trace_beyond(&mut trace, &time) {
+                    return PeekResult::NotReady;
+                }
+                responder.respond(read_items(&mut trace, time, None).len());
+                PeekResult::Done
+            }));
+        }
+
+        fn merge(responses: Vec<usize>) -> usize {
+            responses.into_iter().sum()
+        }
+
+        fn handle_update(_update: (), _state: WorkerState<'_>) {}
+    }
+
+    #[test]
+    fn test_hosted_apps() {
+        let runtime = Runtime::builder(AppConfig::new(2))
+            .app(&TestApp)
+            .app(&CountApp)
+            .start();
+        let items = runtime.handle::<TestApp>("test");
+        let count = runtime.handle::<CountApp>("count");
+        assert!(runtime.try_handle::<CountApp>("test").is_none());
+        assert!(runtime.try_handle::<TestApp>("missing").is_none());
+
+        items.update_batch(vec![Update::Put(1, 10), Update::Put(2, 20)]);
+        let token = items.update(Update::Put(3, 30));
+        // the other app reads the trace at the token of the first one
+        assert_eq!(count.query_at_least(token, ()), 3);
+        assert_eq!(items.query(Query::Get(2)), vec![item(2, 20)]);
+        // both apps share the timestamps
+        let token = count.update(());
+        assert_eq!(items.query_at_least(token, Query::All).len(), 3);
+        // applied through the input ref the hosted app's dataflow returned
+        let token = items.update(Update::Apply(vec![(4, Some(40)), (1, None)]));
+        assert_eq!(count.query_at_least(token, ()), 3);
+        assert_eq!(items.query(Query::Get(4)), vec![item(4, 40)]);
+
+        let internal = runtime.collect_internal_data();
+        assert_eq!(internal.workers.len(), 2);
+        assert!(runtime.shutdown().unwrap().is_clean());
+    }
+
+    #[test]
+    #[should_panic(expected = "an app is already hosted as test")]
+    fn test_duplicate_app() {
+        let _ = Runtime::builder(AppConfig::new(1))
+            .app(&TestApp)
+            .app(&TestApp);
+    }
+}
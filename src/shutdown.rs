@@ -0,0 +1,19 @@
+#[derive(Clone, Debug)]
+pub struct ShutdownReport {
+    // ordered by worker index
+    pub workers: Vec<WorkerReport>,
+}
+
+impl ShutdownReport {
+    /// All workers exited without panicking.
+    pub fn is_clean(&self) -> bool {
+        self.workers.iter().all(|w| w.result.is_ok())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkerReport {
+    pub index: usize,
+    /// the panic message if the worker panicked.
+    pub result: Result<(), String>,
+}
@@ -0,0 +1,278 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use differential_dataflow::consolidation::consolidate_updates;
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{BatchReader, Cursor, TraceReader};
+use timely::progress::Antichain;
+
+use crate::channel::{Receiver, Sender};
+use crate::timely_util::trace_group::TraceGroup;
+use crate::{AppState, Error, PeekResult, PeekTask, SysDiff, SysTime};
+
+type SubscribeFn =
+    dyn Fn(&mut TraceGroup<SysTime>, usize, SysTime) -> SubscriptionTask + Send + Sync;
+
+/// Installs a subscription task on a worker, given the worker's index and the snapshot time.
+#[derive(Clone)]
+pub(crate) struct Subscriber(pub(crate) Arc<SubscribeFn>);
+
+impl fmt::Debug for Subscriber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Subscriber { .. }")
+    }
+}
+
+/// A subscription on one worker, polled like a peek until it is done.
+pub(crate) struct SubscriptionTask {
+    // the name of the trace it reads
+    pub(crate) trace: String,
+    pub(crate) task: PeekTask,
+    // ends the subscription with an error, instead of polling the task
+    pub(crate) fail: Box<dyn FnOnce(Error)>,
+}
+
+pub(crate) enum Message<D> {
+    Updates {
+        worker: usize,
+        updates: Vec<(D, SysTime, SysDiff)>,
+        // `None` if the trace is closed
+        upper: Option<SysTime>,
+    },
+    // the worker could not find the trace
+    Error(Error),
+}
+
+/// Subscribes to the trace registered as `name`.
+/// Without a `name` the subscription reads the type-keyed trace of `Tr`.
+pub(crate) fn subscriber<Tr, K, V, F>(
+    name: Option<String>,
+    filter: F,
+    tx: Sender<Message<(K, V)>>,
+) -> Subscriber
+where
+    Tr: TraceReader<Time = SysTime> + Clone + 'static,
+    for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+    for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+    Tr::Diff: TryInto<SysDiff>,
+    K: Ord + Clone + Send + 'static,
+    V: Ord + Clone + Send + 'static,
+    F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+{
+    let filter = Arc::new(filter);
+    Subscriber(Arc::new(move |trace_group, worker, as_of| {
+        let name = match &name {
+            Some(name) => name.clone(),
+            None => trace_group.type_keyed_name::<Tr>().to_string(),
+        };
+        let tx = tx.clone();
+        let fail_tx = tx.clone();
+        let fail = Box::new(move |e| {
+            let _ = fail_tx.send(Message::Error(e));
+        });
+        let trace = match trace_group.get_named::<Tr>(&name) {
+            Ok(trace) => trace,
+            Err(e) => {
+                fail(e);
+                return SubscriptionTask {
+                    trace: name,
+                    task: Box::new(|| PeekResult::Done),
+                    fail: Box::new(|_| {}),
+                };
+            }
+        };
+        let mut trace = trace.clone();
+        trace.set_logical_compaction(Antichain::from_elem(as_of).borrow());
+        let filter = filter.clone();
+        // times before `lower` are reported
+        let mut lower = SysTime::minimum();
+        let task = Box::new(move || {
+            let mut upper = Antichain::new();
+            trace.read_upper(&mut upper);
+            let upper = upper.into_option();
+            let ready = match upper {
+                Some(upper) => upper > as_of && upper > lower,
+                None => true,
+            };
+            if !ready {
+                return PeekResult::NotReady;
+            }
+            let updates = read_updates(&mut trace, lower, as_of, &*filter);
+            let msg = Message::Updates {
+                worker,
+                updates,
+                upper,
+            };
+            let Some(upper) = upper else {
+                let _ = tx.send(msg);
+                return PeekResult::Done;
+            };
+            if tx.send(msg).is_err() {
+                // the subscription was dropped
+                return PeekResult::Done;
+            }
+            lower = upper;
+            // keep times from `upper` on distinguishable, let everything before merge
+            let since = upper.step_back().expect("upper is beyond as_of");
+            trace.set_logical_compaction(Antichain::from_elem(since).borrow());
+            trace.set_physical_compaction(Antichain::from_elem(upper).borrow());
+            PeekResult::NotReady
+        });
+        SubscriptionTask {
+            trace: name,
+            task,
+            fail,
+        }
+    }))
+}
+
+/// Updates at times not before `lower`, times before `as_of` are advanced to it.
+fn read_updates<Tr, K, V>(
+    trace: &mut Tr,
+    lower: SysTime,
+    as_of: SysTime,
+    filter: &dyn Fn(&K, &V) -> bool,
+) -> Vec<((K, V), SysTime, SysDiff)>
+where
+    Tr: TraceReader<Time = SysTime>,
+    for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+    for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+    Tr::Diff: TryInto<SysDiff>,
+    K: Ord + Clone,
+    V: Ord + Clone,
+{
+    // batches entirely before `lower` were reported already
+    let mut batches = vec![];
+    trace.map_batches(|batch| {
+        if !batch.upper().less_equal(&lower) {
+            batches.push(batch.clone());
+        }
+    });
+
+    let mut ret = vec![];
+    for batch in batches {
+        let mut cursor = batch.cursor();
+        while cursor.key_valid(&batch) {
+            let key = cursor.key(&batch).into_owned();
+            while cursor.val_valid(&batch) {
+                let val = cursor.val(&batch).into_owned();
+                if filter(&key, &val) {
+                    let mut times = vec![];
+                    cursor.map_times(&batch, |time, diff| {
+                        let time = time.into_owned();
+                        if time >= lower {
+                            let diff: SysDiff = diff
+                                .into_owned()
+                                .try_into()
+                                .unwrap_or_else(|_| panic!("diff out of range"));
+                            times.push((std::cmp::max(time, as_of), diff));
+                        }
+                    });
+                    for (time, diff) in times {
+                        ret.push(((key.clone(), val.clone()), time, diff));
+                    }
+                }
+                cursor.step_val(&batch);
+            }
+            cursor.step_key(&batch);
+        }
+    }
+    consolidate_updates(&mut ret);
+    ret
+}
+
+/// A change feed of a trace, starting with a snapshot at the time it was created.
+///
+/// Updates of all workers are merged and only released once every worker passed their time.
+pub struct Subscription<D> {
+    rx: Receiver<Message<D>>,
+    state: Arc<Mutex<AppState>>,
+    // reported by each worker, `None` if its trace is closed
+    uppers: Vec<Option<SysTime>>,
+    // updates before `upper` are released
+    upper: SysTime,
+    pending: Vec<(D, SysTime, SysDiff)>,
+}
+
+impl<D: Ord> Subscription<D> {
+    pub(crate) fn new(
+        rx: Receiver<Message<D>>,
+        workers: usize,
+        as_of: SysTime,
+        state: Arc<Mutex<AppState>>,
+    ) -> Self {
+        Subscription {
+            rx,
+            state,
+            uppers: vec![Some(as_of); workers],
+            upper: as_of,
+            pending: vec![],
+        }
+    }
+
+    /// All times before it are released.
+    pub fn upper(&self) -> SysTime {
+        self.upper
+    }
+
+    /// Block until some times are complete, returns their updates ordered by time.
+    pub fn next(&mut self) -> Result<Vec<(D, SysTime, SysDiff)>, Error> {
+        loop {
+            let msg = self.rx.recv().map_err(|_| self.disconnected())?;
+            if let Some(updates) = self.accept(msg)? {
+                return Ok(updates);
+            }
+        }
+    }
+
+    pub async fn next_async(&mut self) -> Result<Vec<(D, SysTime, SysDiff)>, Error> {
+        loop {
+            let msg = self
+                .rx
+                .recv_async()
+                .await
+                .map_err(|_| self.disconnected())?;
+            if let Some(updates) = self.accept(msg)? {
+                return Ok(updates);
+            }
+        }
+    }
+
+    fn accept(&mut self, msg: Message<D>) -> Result<Option<Vec<(D, SysTime, SysDiff)>>, Error> {
+        let (worker, updates, upper) = match msg {
+            Message::Updates {
+                worker,
+                updates,
+                upper,
+            } => (worker, updates, upper),
+            Message::Error(e) => return Err(e),
+        };
+        self.pending.extend(updates);
+        self.uppers[worker] = upper;
+
+        let upper = self
+            .uppers
+            .iter()
+            .map(|upper| upper.unwrap_or(SysTime::MAX))
+            .min()
+            .expect("at least one worker");
+        if upper <= self.upper {
+            return Ok(None);
+        }
+        self.upper = upper;
+        let (mut ready, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|(_, time, _)| *time < upper);
+        self.pending = pending;
+        consolidate_updates(&mut ready);
+        ready.sort_by(|(d1, t1, _), (d2, t2, _)| (t1, d1).cmp(&(t2, d2)));
+        Ok(Some(ready))
+    }
+
+    fn disconnected(&self) -> Error {
+        match self.state.lock().unwrap().check() {
+            Ok(()) => Error::Disconnected,
+            Err(e) => e,
+        }
+    }
+}
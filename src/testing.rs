@@ -0,0 +1,254 @@
+//! A small app for the tests, items keyed by `u64` in an arranged upsert input.
+
+use std::future::Future;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::Duration;
+
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{Cursor, TraceReader};
+use serde::{Deserialize, Serialize};
+use timely::dataflow::Scope;
+
+use crate::timely_util::trace_beyond;
+use crate::timely_util::upsert_input::{UpsertInput, UpsertRef, UpsertTrace};
+use crate::{App, PeekResult, Responder, Route, SysDiff, SysTime, WorkerState};
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) struct Item {
+    pub(crate) key: u64,
+    pub(crate) value: i64,
+}
+
+impl UpsertInput for Item {
+    type Key = u64;
+
+    fn get_key(&self) -> u64 {
+        self.key
+    }
+}
+
+pub(crate) type ItemTrace = UpsertTrace<Item, SysTime, SysDiff>;
+
+#[derive(Clone)]
+pub(crate) struct TestApp;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum Query {
+    Get(u64),
+    All,
+    // never answered, until the query is cancelled or the app fails
+    Never,
+    // like `Never`, but holds the trace registered as the name
+    Hold(String),
+    // every worker drops its responder without responding
+    Drop,
+    // like `Get`, but read right away instead of waiting for the traces to pass the time
+    Unchecked(u64),
+    // like `All`, but not ready for the given number of polls after the traces passed the time
+    Slow(u32),
+    Panic,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum Update {
+    Put(u64, i64),
+    Delete(u64),
+    // sent to the input as one batch, by worker 0
+    PutBatch(Vec<(u64, i64)>),
+    DeleteBatch(Vec<u64>),
+    Apply(Vec<(u64, Option<i64>)>),
+    // every worker puts the value at its own index as key
+    PutEach(i64),
+    // panics on the worker with the index
+    Panic(usize),
+    // blocks the worker with the index for some milliseconds
+    Sleep(usize, u64),
+    // routed to a worker which does not exist
+    Misroute(u64),
+}
+
+pub(crate) fn item(key: u64, value: i64) -> Item {
+    Item { key, value }
+}
+
+/// Run `future` on the current thread, parking it while the future is pending.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Addresses of free ports on 127.0.0.1, to run a cluster of `processes` within a test.
+pub(crate) fn local_addresses(processes: usize) -> Vec<String> {
+    let listeners: Vec<_> = (0..processes)
+        .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect();
+    listeners
+        .iter()
+        .map(|l| l.local_addr().unwrap().to_string())
+        .collect()
+}
+
+/// Items in `trace` at `time`, only `key` if given.
+pub(crate) fn read_items(trace: &mut ItemTrace, time: SysTime, key: Option<u64>) -> Vec<Item> {
+    let mut ret = vec![];
+    let (mut cursor, storage) = trace.cursor();
+    if let Some(key) = key {
+        cursor.seek_key(&storage, &key);
+    }
+    while let Some(k) = cursor.get_key(&storage) {
+        if key.is_some_and(|key| key != *k) {
+            break;
+        }
+        while let Some(val) = cursor.get_val(&storage) {
+            let mut count: SysDiff = 0;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    count += diff.into_owned();
+                }
+            });
+            assert!(count == 0 || count == 1, "invalid count: {count}");
+            if count == 1 {
+                ret.push(val.into_owned());
+            }
+            cursor.step_val(&storage);
+        }
+        cursor.step_key(&storage);
+    }
+    ret
+}
+
+impl App for TestApp {
+    type Query = Query;
+    type Update = Update;
+    type Response = Vec<Item>;
+    type State = UpsertRef<Item>;
+
+    fn name(&self) -> &str {
+        "test"
+    }
+
+    fn dataflow<G: Scope<Timestamp = SysTime>>(
+        scope: &mut G,
+        state: WorkerState<'_>,
+    ) -> UpsertRef<Item> {
+        let (_, items) = state
+            .upsert_input_group
+            .alloc_arranged::<Item, _>(scope, state.trace_group);
+        items
+    }
+
+    fn handle_query(
+        query: Query,
+        time: SysTime,
+        state: WorkerState<'_, UpsertRef<Item>>,
+        mut responder: Responder<Vec<Item>>,
+    ) {
+        let mut polls = 0;
+        let key = match query {
+            Query::Unchecked(key) => {
+                let mut trace = state.trace_group.get::<ItemTrace>().unwrap().clone();
+                responder.respond(read_items(&mut trace, time, Some(key)));
+                return;
+            }
+            Query::Get(key) => Some(key),
+            Query::All => None,
+            Query::Slow(n) => {
+                polls = n;
+                None
+            }
+            Query::Never => {
+                state.peeks.push(Box::new(move || {
+                    let _ = &responder;
+                    PeekResult::NotReady
+                }));
+                return;
+            }
+            Query::Hold(name) => {
+                let trace = state.trace_group.get_named::<ItemTrace>(&name).unwrap();
+                let trace = trace.clone();
+                state.peeks.push(Box::new(move || {
+                    let _ = (&trace, &responder);
+                    PeekResult::NotReady
+                }));
+                return;
+            }
+            Query::Drop => return,
+            Query::Panic => panic!("query panicked"),
+        };
+        let mut trace = state.trace_group.get::<ItemTrace>().unwrap().clone();
+        state.peeks.push(Box::new(move || {
+            if !trace_beyond(&mut trace, &time) {
+                return PeekResult::NotReady;
+            }
+            if polls > 0 {
+                polls -= 1;
+                return PeekResult::NotReady;
+            }
+            responder.respond(read_items(&mut trace, time, key));
+            PeekResult::Done
+        }));
+    }
+
+    fn merge(responses: Vec<Vec<Item>>) -> Vec<Item> {
+        let mut items: Vec<_> = responses.into_iter().flatten().collect();
+        items.sort();
+        items
+    }
+
+    fn handle_update(update: Update, state: WorkerState<'_, UpsertRef<Item>>) {
+        match update {
+            Update::Put(key, value) => state.upsert_input_group.upsert(item(key, value)),
+            Update::Delete(key) => state.upsert_input_group.delete::<Item>(key),
+            Update::PutBatch(items) => {
+                let items = items.into_iter().map(|(key, value)| item(key, value));
+                state.upsert_input_group.upsert_batch(items)
+            }
+            Update::DeleteBatch(keys) => state.upsert_input_group.delete_batch::<Item>(keys),
+            Update::Apply(updates) => {
+                let updates = updates
+                    .into_iter()
+                    .map(|(key, value)| (key, value.map(|value| item(key, value))));
+                state.upsert_input_group.apply_to(state.app, updates)
+            }
+            Update::PutEach(_) => unreachable!("broadcast updates are built per worker"),
+            Update::Panic(_) => panic!("update panicked"),
+            Update::Sleep(_, millis) => std::thread::sleep(Duration::from_millis(millis)),
+            Update::Misroute(_) => unreachable!("misrouted update applied"),
+        }
+    }
+
+    /// Each key lives on one worker.
+    fn route_update(update: Update, workers: usize) -> Route<Update> {
+        match update {
+            Update::Put(key, _) | Update::Delete(key) => {
+                Route::Worker(key as usize % workers, update)
+            }
+            Update::PutBatch(_) | Update::DeleteBatch(_) | Update::Apply(_) => {
+                Route::Worker(0, update)
+            }
+            Update::PutEach(value) => {
+                Route::Broadcast(Box::new(move |idx| Update::Put(idx as u64, value)))
+            }
+            Update::Panic(idx) | Update::Sleep(idx, _) => Route::Worker(idx, update),
+            Update::Misroute(_) => Route::Worker(workers, update),
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use std::any::{type_name, TypeId};
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use differential_dataflow::trace::cursor::IntoOwned;
 use differential_dataflow::trace::{Cursor, TraceReader};
@@ -7,8 +10,58 @@ use timely::PartialOrder;
 
 pub mod dd_input;
 pub mod trace_group;
+mod upsert;
 pub mod upsert_input;
 
+/// The names the type-keyed methods of a group registered entries under, by the entry's type.
+///
+/// Type names are not unique, so a name already taken gets a suffix.
+pub(crate) struct TypeNames {
+    names: BTreeMap<TypeId, Arc<str>>,
+}
+
+impl TypeNames {
+    pub(crate) fn new() -> Self {
+        TypeNames {
+            names: BTreeMap::new(),
+        }
+    }
+
+    /// Name the entry of `X` after `base`, panics if `X` has an entry already.
+    pub(crate) fn insert<X: 'static>(
+        &mut self,
+        base: &str,
+        taken: impl Fn(&str) -> bool,
+        what: &str,
+    ) -> String {
+        let tid = TypeId::of::<X>();
+        assert!(
+            !self.names.contains_key(&tid),
+            "register same {what}: {base}"
+        );
+        let mut name = base.to_string();
+        let mut n = 1;
+        while taken(&name) {
+            n += 1;
+            name = format!("{base}#{n}");
+        }
+        self.names.insert(tid, name.as_str().into());
+        name
+    }
+
+    /// The name of `X`'s entry, or its type name so a lookup fails naming the type.
+    pub(crate) fn get<X: 'static>(&self) -> Arc<str> {
+        match self.names.get(&TypeId::of::<X>()) {
+            Some(name) => name.clone(),
+            None => type_name::<X>().into(),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) {
+        self.names.retain(|_, n| &**n != name);
+    }
+}
+
 pub fn trace_beyond<T, Tr>(tr: &mut Tr, time: &T) -> bool
 where
     Tr: TraceReader<Time = T> + 'static,
@@ -57,3 +110,38 @@ where
     }
     ret
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    #[test]
+    fn test_type_names() {
+        let mut names = TypeNames::new();
+        let mut taken = BTreeSet::new();
+        let name = names.insert::<u8>("n", |n| taken.contains(n), "input");
+        assert_eq!(name, "n");
+        taken.insert(name);
+        // another type with the same name gets a suffix
+        let name = names.insert::<u16>("n", |n| taken.contains(n), "input");
+        assert_eq!(name, "n#2");
+        assert_eq!(&*names.get::<u8>(), "n");
+        assert_eq!(&*names.get::<u16>(), "n#2");
+        // a type without an entry falls back to its type name
+        assert_eq!(&*names.get::<u32>(), "u32");
+
+        names.remove("n");
+        assert_eq!(&*names.get::<u8>(), "u8");
+        assert_eq!(&*names.get::<u16>(), "n#2");
+    }
+
+    #[test]
+    #[should_panic(expected = "register same input: n")]
+    fn test_type_names_twice() {
+        let mut names = TypeNames::new();
+        names.insert::<u8>("n", |_| false, "input");
+        names.insert::<u8>("n", |_| false, "input");
+    }
+}
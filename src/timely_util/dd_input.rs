@@ -1,29 +1,76 @@
-use std::any::{type_name, Any, TypeId};
+use std::any::{type_name, Any};
 use std::collections::BTreeMap;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use differential_dataflow::difference::Semigroup;
 use differential_dataflow::input::InputSession;
-use differential_dataflow::Collection;
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::arrange::{ArrangeBySelf, TraceAgent};
+use differential_dataflow::trace::implementations::ord_neu::OrdKeySpine;
+use differential_dataflow::{Collection, ExchangeData, Hashable};
 use timely::dataflow::operators::Input as TimelyInput;
+use timely::dataflow::Scope;
 use timely::progress::Timestamp;
 
+use crate::timely_util::trace_group::TraceGroup;
+use crate::timely_util::TypeNames;
+use crate::Error;
+
+type SharedTrace<D, T, R> = TraceAgent<OrdKeySpine<D, T, R>>;
+
 struct Bundle<T> {
     handle: Box<dyn Any>,
-    name: &'static str,
     advance_fn: Box<dyn Fn(&mut Box<dyn Any>, T)>,
     flush_fn: Box<dyn Fn(&mut Box<dyn Any>)>,
     get_time_fn: Box<dyn Fn(&mut Box<dyn Any>) -> T>,
 }
 
 pub(crate) struct BundleInfo<T> {
-    pub(crate) name: &'static str,
+    pub(crate) name: String,
     pub(crate) time: T,
 }
 
+/// Names an input of `D` registered in a `DDInputGroup`.
+pub struct InputRef<D> {
+    name: Arc<str>,
+    _marker: PhantomData<fn() -> D>,
+}
+
+impl<D> InputRef<D> {
+    /// Refs are handed out by `register` and the `alloc_*` methods.
+    pub(crate) fn new(name: impl Into<Arc<str>>) -> Self {
+        InputRef {
+            name: name.into(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<D> Clone for InputRef<D> {
+    fn clone(&self) -> Self {
+        InputRef::new(self.name.clone())
+    }
+}
+
+impl<D> fmt::Debug for InputRef<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InputRef({})", self.name)
+    }
+}
+
+/// Inputs by name, the type-keyed methods find an input by the type of `D`.
 pub struct DDInputGroup<T, R> {
-    inputs: BTreeMap<TypeId, Bundle<T>>,
+    inputs: BTreeMap<String, Bundle<T>>,
+    // names of the inputs registered by type
+    types: TypeNames,
+    // arranged inputs by the input's name, dataflows installed later import them
+    shared: TraceGroup<T>,
     _marker: PhantomData<R>,
 }
 
@@ -35,17 +82,40 @@ where
     pub fn new() -> Self {
         DDInputGroup {
             inputs: BTreeMap::new(),
+            types: TypeNames::new(),
+            shared: TraceGroup::new(),
             _marker: PhantomData,
         }
     }
 
-    pub fn register<D>(&mut self, handle: InputSession<T, D, R>)
+    pub fn register<D>(&mut self, handle: InputSession<T, D, R>) -> InputRef<D>
     where
         D: Clone + Ord + Debug + 'static,
     {
-        let tid = TypeId::of::<D>();
-        let name = type_name::<D>();
+        let name = self.type_keyed_name::<D>();
+        self.register_named(name, handle)
+    }
+
+    /// Name the type-keyed input of `D` after its type.
+    fn type_keyed_name<D: 'static>(&mut self) -> String {
+        let inputs = &self.inputs;
+        self.types.insert::<D>(
+            type_name::<D>(),
+            |name| inputs.contains_key(name),
+            "InputSession",
+        )
+    }
 
+    /// Register `handle` under `name`, so several inputs of the same type can be registered.
+    pub fn register_named<D>(
+        &mut self,
+        name: impl Into<String>,
+        handle: InputSession<T, D, R>,
+    ) -> InputRef<D>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        let name = name.into();
         let handle = Box::new(handle);
         let advance_fn = Box::new(|any: &mut Box<dyn Any>, t: T| {
             let handle: &mut InputSession<T, D, R> = any.downcast_mut().unwrap();
@@ -61,31 +131,79 @@ where
         });
         let bundle = Bundle {
             handle,
-            name,
             advance_fn,
             flush_fn,
             get_time_fn,
         };
-        let d = self.inputs.insert(tid, bundle);
-        assert!(d.is_none(), "register same InputSession");
+        let d = self.inputs.insert(name.clone(), bundle);
+        assert!(d.is_none(), "register same InputSession: {name}");
+        InputRef::new(name)
+    }
+
+    pub fn get<D>(&self) -> Result<&InputSession<T, D, R>, Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        self.get_named(&self.types.get::<D>())
+    }
+
+    pub fn get_mut<D>(&mut self) -> Result<&mut InputSession<T, D, R>, Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        let name = self.types.get::<D>();
+        self.get_named_mut(&name)
+    }
+
+    /// Fails if no input is registered under `name` or it does not take `D`.
+    pub fn get_named<D>(&self, name: &str) -> Result<&InputSession<T, D, R>, Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        let input = self.inputs.get(name).and_then(|b| b.handle.downcast_ref());
+        input.ok_or_else(|| self.not_registered::<D>(name))
+    }
+
+    pub fn get_named_mut<D>(&mut self, name: &str) -> Result<&mut InputSession<T, D, R>, Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        let found = self
+            .inputs
+            .get(name)
+            .is_some_and(|b| b.handle.is::<InputSession<T, D, R>>());
+        if !found {
+            return Err(self.not_registered::<D>(name));
+        }
+        Ok(self
+            .inputs
+            .get_mut(name)
+            .unwrap()
+            .handle
+            .downcast_mut()
+            .unwrap())
+    }
+
+    fn not_registered<D>(&self, name: &str) -> Error {
+        Error::NotRegistered {
+            name: name.to_string(),
+            type_name: type_name::<D>(),
+            registered: self.inputs.keys().cloned().collect(),
+        }
     }
 
-    pub fn get<D>(&self) -> Option<&InputSession<T, D, R>>
+    pub fn input<D>(&self, input: &InputRef<D>) -> Result<&InputSession<T, D, R>, Error>
     where
         D: Clone + Ord + Debug + 'static,
     {
-        let tid = TypeId::of::<D>();
-        let bundle = self.inputs.get(&tid)?;
-        Some(bundle.handle.downcast_ref().unwrap())
+        self.get_named(input.name())
     }
 
-    pub fn get_mut<D>(&mut self) -> Option<&mut InputSession<T, D, R>>
+    pub fn input_mut<D>(&mut self, input: &InputRef<D>) -> Result<&mut InputSession<T, D, R>, Error>
     where
         D: Clone + Ord + Debug + 'static,
     {
-        let tid = TypeId::of::<D>();
-        let bundle = self.inputs.get_mut(&tid)?;
-        Some(bundle.handle.downcast_mut().unwrap())
+        self.get_named_mut(input.name())
     }
 
     pub fn insert_batch<D>(&mut self, batch: impl IntoIterator<Item = D>)
@@ -93,37 +211,184 @@ where
         D: Clone + Ord + Debug + 'static,
         R: From<u8>,
     {
-        let handle = self.get_mut::<D>().expect("not registered");
+        self.try_insert_batch(batch).unwrap()
+    }
+
+    pub fn try_insert_batch<D>(&mut self, batch: impl IntoIterator<Item = D>) -> Result<(), Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+        R: From<u8>,
+    {
+        let input = InputRef::new(self.types.get::<D>());
+        self.try_insert_batch_to(&input, batch)
+    }
+
+    /// Like `insert_batch`, for the input `input` refers to.
+    pub fn insert_batch_to<D>(&mut self, input: &InputRef<D>, batch: impl IntoIterator<Item = D>)
+    where
+        D: Clone + Ord + Debug + 'static,
+        R: From<u8>,
+    {
+        self.try_insert_batch_to(input, batch).unwrap()
+    }
+
+    pub fn try_insert_batch_to<D>(
+        &mut self,
+        input: &InputRef<D>,
+        batch: impl IntoIterator<Item = D>,
+    ) -> Result<(), Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+        R: From<u8>,
+    {
+        let handle = self.input_mut(input)?;
         for d in batch {
             handle.update(d, 1.into());
         }
+        Ok(())
     }
 
     pub fn update<D>(&mut self, value: D, change: R)
     where
         D: Clone + Ord + Debug + 'static,
     {
-        let handle = self.get_mut::<D>().expect("not registered");
-        handle.update(value, change);
+        self.try_update(value, change).unwrap()
+    }
+
+    pub fn try_update<D>(&mut self, value: D, change: R) -> Result<(), Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        let input = InputRef::new(self.types.get::<D>());
+        self.try_update_to(&input, value, change)
+    }
+
+    /// Like `update`, for the input `input` refers to.
+    pub fn update_to<D>(&mut self, input: &InputRef<D>, value: D, change: R)
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        self.try_update_to(input, value, change).unwrap()
+    }
+
+    pub fn try_update_to<D>(
+        &mut self,
+        input: &InputRef<D>,
+        value: D,
+        change: R,
+    ) -> Result<(), Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        self.input_mut(input)?.update(value, change);
+        Ok(())
     }
 
     pub fn update_at<D>(&mut self, value: D, time: T, change: R)
     where
         D: Clone + Ord + Debug + 'static,
     {
-        let handle = self.get_mut::<D>().expect("not registered");
-        handle.update_at(value, time, change);
+        self.try_update_at(value, time, change).unwrap()
     }
 
-    pub fn alloc_collection<D, G>(&mut self, scope: &mut G) -> Collection<G, D, R>
+    pub fn try_update_at<D>(&mut self, value: D, time: T, change: R) -> Result<(), Error>
+    where
+        D: Clone + Ord + Debug + 'static,
+    {
+        self.get_mut::<D>()?.update_at(value, time, change);
+        Ok(())
+    }
+
+    /// The returned ref finds the input again, e.g. in `App::handle_update`.
+    pub fn alloc_collection<D, G>(&mut self, scope: &mut G) -> (Collection<G, D, R>, InputRef<D>)
     where
         G: TimelyInput<Timestamp = T>,
         D: Clone + Ord + Debug + 'static,
     {
-        let input: InputSession<T, D, R> = InputSession::new();
-        self.register(input);
-        let handle = self.get_mut::<D>().unwrap();
-        handle.to_collection(scope)
+        let name = self.type_keyed_name::<D>();
+        self.alloc_collection_named(name, scope)
+    }
+
+    pub fn alloc_collection_named<D, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+    ) -> (Collection<G, D, R>, InputRef<D>)
+    where
+        G: TimelyInput<Timestamp = T>,
+        D: Clone + Ord + Debug + 'static,
+    {
+        let input = self.register_named(name, InputSession::<T, D, R>::new());
+        let handle = self.input_mut(&input).unwrap();
+        (handle.to_collection(scope), input)
+    }
+
+    /// Like `alloc_collection`, but also arrange the input so views can import it.
+    pub fn alloc_shared_collection<D, G>(
+        &mut self,
+        scope: &mut G,
+    ) -> (Collection<G, D, R>, InputRef<D>)
+    where
+        G: Scope<Timestamp = T>,
+        D: ExchangeData + Hashable + Debug,
+        T: Lattice,
+        R: ExchangeData,
+    {
+        let name = self.type_keyed_name::<D>();
+        self.alloc_shared_collection_named(name, scope)
+    }
+
+    pub fn alloc_shared_collection_named<D, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+    ) -> (Collection<G, D, R>, InputRef<D>)
+    where
+        G: Scope<Timestamp = T>,
+        D: ExchangeData + Hashable + Debug,
+        T: Lattice,
+        R: ExchangeData,
+    {
+        let (collection, input) = self.alloc_collection_named::<D, G>(name, scope);
+        let arranged = collection.arrange_by_self();
+        self.shared
+            .register_trace_named(input.name(), arranged.trace);
+        (collection, input)
+    }
+
+    /// Import an input allocated by `alloc_shared_collection` into another dataflow.
+    pub fn import_collection<D, G>(&mut self, scope: &mut G) -> Result<Collection<G, D, R>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        D: ExchangeData + Hashable + Debug,
+        T: Lattice,
+        R: ExchangeData,
+    {
+        let name = self.types.get::<D>();
+        self.import_collection_named(&name, scope)
+    }
+
+    pub fn import_collection_named<D, G>(
+        &mut self,
+        name: &str,
+        scope: &mut G,
+    ) -> Result<Collection<G, D, R>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        D: ExchangeData + Hashable + Debug,
+        T: Lattice,
+        R: ExchangeData,
+    {
+        let trace = self.shared.get_named_mut::<SharedTrace<D, T, R>>(name)?;
+        Ok(trace.import(scope).as_collection(|d, _| d.clone()))
+    }
+
+    pub(crate) fn logical_compaction(&mut self, frontier: T) {
+        self.shared.logical_compaction(frontier);
+    }
+
+    pub(crate) fn physical_compaction(&mut self) {
+        self.shared.physical_compaction();
     }
 
     pub fn advance_and_flush(&mut self, frontier: T) {
@@ -135,13 +400,42 @@ where
 
     pub(crate) fn collect_info(&mut self) -> Vec<BundleInfo<T>> {
         let mut ret = vec![];
-        for bundle in self.inputs.values_mut() {
+        for (name, bundle) in self.inputs.iter_mut() {
             let time = (bundle.get_time_fn)(&mut bundle.handle);
             ret.push(BundleInfo {
-                name: bundle.name,
+                name: name.clone(),
                 time,
             });
         }
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SysDiff, SysTime};
+
+    #[test]
+    fn test_try_updates() {
+        let mut group = DDInputGroup::<SysTime, SysDiff>::new();
+        let err = group.try_update(1u64, 1).unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+        assert!(group.try_insert_batch([1u64]).is_err());
+
+        let numbers = group.register_named::<u64>("numbers", InputSession::new());
+        group.try_update_to(&numbers, 1, 1).unwrap();
+        group.try_insert_batch_to(&numbers, [2, 3]).unwrap();
+        // the type-keyed methods do not find a named input
+        assert!(group.try_update(1u64, -1).is_err());
+
+        let missing = InputRef::<u64>::new("missing");
+        assert!(group.try_update_to(&missing, 1, 1).is_err());
+        let wrong_type = InputRef::<u32>::new("numbers");
+        assert!(group.try_update_to(&wrong_type, 1, 1).is_err());
+
+        group.register(InputSession::<SysTime, u64, SysDiff>::new());
+        group.try_update(1u64, 1).unwrap();
+        group.try_update_at(1u64, 5.into(), -1).unwrap();
+    }
+}
@@ -1,15 +1,23 @@
-use std::any::{type_name, Any, TypeId};
-use std::collections::BTreeMap;
+use std::any::{type_name, Any};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 use differential_dataflow::trace::TraceReader;
 use timely::progress::{Antichain, Timestamp};
 
+use crate::timely_util::TypeNames;
+use crate::Error;
+
 struct Bundle<T> {
     trace: Box<dyn Any>,
     name: String,
     physical_compaction_fn: Box<dyn Fn(&mut Box<dyn Any>)>,
     logical_compaction_fn: Box<dyn Fn(&mut Box<dyn Any>, T)>,
     get_compaction_fn: Box<dyn Fn(&mut Box<dyn Any>) -> (Antichain<T>, Antichain<T>)>,
+    upper_fn: Box<dyn Fn(&mut Box<dyn Any>) -> Antichain<T>>,
 }
 
 pub(crate) struct BundleInfo<T> {
@@ -41,18 +49,61 @@ impl<T: Clone> Bundle<T> {
             let physical = trace.get_physical_compaction().to_owned();
             (logical, physical)
         });
+        let upper_fn = Box::new(|any: &mut Box<dyn Any>| {
+            let trace: &mut Tr = any.downcast_mut().unwrap();
+            let mut upper = Antichain::new();
+            trace.read_upper(&mut upper);
+            upper
+        });
         Bundle {
             trace,
             name,
             physical_compaction_fn,
             logical_compaction_fn,
             get_compaction_fn,
+            upper_fn,
+        }
+    }
+}
+
+/// Names a trace of type `Tr` registered in a `TraceGroup`.
+pub struct TraceRef<Tr> {
+    name: Arc<str>,
+    _marker: PhantomData<fn() -> Tr>,
+}
+
+impl<Tr> TraceRef<Tr> {
+    pub fn new(name: impl Into<Arc<str>>) -> Self {
+        TraceRef {
+            name: name.into(),
+            _marker: PhantomData,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<Tr> Clone for TraceRef<Tr> {
+    fn clone(&self) -> Self {
+        TraceRef::new(self.name.clone())
+    }
 }
 
+impl<Tr> fmt::Debug for TraceRef<Tr> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceRef({})", self.name)
+    }
+}
+
+/// Traces by name, the type-keyed methods find a trace by its type.
 pub struct TraceGroup<T> {
-    traces: BTreeMap<TypeId, Bundle<T>>,
+    traces: BTreeMap<String, Bundle<T>>,
+    // names of the traces registered by type
+    types: TypeNames,
+    // names of the traces handed out since the last `take_reads`
+    reads: RefCell<BTreeSet<String>>,
 }
 
 impl<T> TraceGroup<T>
@@ -62,43 +113,125 @@ where
     pub fn new() -> Self {
         TraceGroup {
             traces: BTreeMap::new(),
+            types: TypeNames::new(),
+            reads: RefCell::default(),
         }
     }
 
-    pub fn register_trace<Tr>(&mut self, trace: Tr)
+    /// Register `trace` for `get::<Tr>`, it is named `Trace[K,V,T,R]` after its types.
+    pub fn register_trace<Tr>(&mut self, trace: Tr) -> TraceRef<Tr>
     where
         Tr: TraceReader<Time = T> + 'static,
     {
-        let tid = TypeId::of::<Tr>();
-        let name = {
+        let base = {
             let key_name = type_name::<Tr::Key<'_>>();
             let value_name = type_name::<Tr::Val<'_>>();
             let time_name = type_name::<Tr::Time>();
             let diff_name = type_name::<Tr::Diff>();
             format!("Trace[{key_name},{value_name},{time_name},{diff_name}]")
         };
+        let traces = &self.traces;
+        let name = self
+            .types
+            .insert::<Tr>(&base, |name| traces.contains_key(name), "trace");
+        self.register_trace_named(name, trace)
+    }
+
+    /// Register `trace` under `name`, so several traces of the same type can be registered.
+    pub fn register_trace_named<Tr>(&mut self, name: impl Into<String>, trace: Tr) -> TraceRef<Tr>
+    where
+        Tr: TraceReader<Time = T> + 'static,
+    {
+        let name = name.into();
+        let bundle = Bundle::new::<Tr>(trace, name.clone());
+        let d = self.traces.insert(name.clone(), bundle);
+        assert!(d.is_none(), "register same trace: {name}");
+        TraceRef::new(name)
+    }
+
+    pub fn get<Tr>(&self) -> Result<&Tr, Error>
+    where
+        Tr: TraceReader<Time = T> + 'static,
+    {
+        self.get_named(&self.types.get::<Tr>())
+    }
+
+    pub fn get_mut<Tr>(&mut self) -> Result<&mut Tr, Error>
+    where
+        Tr: TraceReader<Time = T> + 'static,
+    {
+        let name = self.types.get::<Tr>();
+        self.get_named_mut(&name)
+    }
+
+    /// Fails if no trace is registered under `name` or it is not a `Tr`.
+    pub fn get_named<Tr>(&self, name: &str) -> Result<&Tr, Error>
+    where
+        Tr: TraceReader<Time = T> + 'static,
+    {
+        let trace = self.traces.get(name).and_then(|b| b.trace.downcast_ref());
+        let trace = trace.ok_or_else(|| self.not_registered::<Tr>(name))?;
+        self.reads.borrow_mut().insert(name.to_string());
+        Ok(trace)
+    }
+
+    pub fn get_named_mut<Tr>(&mut self, name: &str) -> Result<&mut Tr, Error>
+    where
+        Tr: TraceReader<Time = T> + 'static,
+    {
+        if !self.traces.get(name).is_some_and(|b| b.trace.is::<Tr>()) {
+            return Err(self.not_registered::<Tr>(name));
+        }
+        self.reads.get_mut().insert(name.to_string());
+        Ok(self
+            .traces
+            .get_mut(name)
+            .unwrap()
+            .trace
+            .downcast_mut()
+            .unwrap())
+    }
 
-        let bundle = Bundle::new::<Tr>(trace, name);
-        let d = self.traces.insert(tid, bundle);
-        assert!(d.is_none(), "register same trace")
+    fn not_registered<Tr>(&self, name: &str) -> Error {
+        Error::NotRegistered {
+            name: name.to_string(),
+            type_name: type_name::<Tr>(),
+            registered: self.traces.keys().cloned().collect(),
+        }
     }
 
-    pub fn get<Tr>(&self) -> Option<&Tr>
+    pub fn trace<Tr>(&self, trace: &TraceRef<Tr>) -> Result<&Tr, Error>
     where
         Tr: TraceReader<Time = T> + 'static,
     {
-        let tid = TypeId::of::<Tr>();
-        let bundle = self.traces.get(&tid)?;
-        Some(bundle.trace.downcast_ref().unwrap())
+        self.get_named(trace.name())
     }
 
-    pub fn get_mut<Tr>(&mut self) -> Option<&mut Tr>
+    pub fn trace_mut<Tr>(&mut self, trace: &TraceRef<Tr>) -> Result<&mut Tr, Error>
     where
         Tr: TraceReader<Time = T> + 'static,
     {
-        let tid = TypeId::of::<Tr>();
-        let bundle = self.traces.get_mut(&tid)?;
-        Some(bundle.trace.downcast_mut().unwrap())
+        self.get_named_mut(trace.name())
+    }
+
+    /// Stop tracking the trace, its handle is dropped so it can be freed.
+    pub(crate) fn remove(&mut self, name: &str) -> bool {
+        self.types.remove(name);
+        self.traces.remove(name).is_some()
+    }
+
+    /// The name `get::<Tr>` looks up.
+    pub(crate) fn type_keyed_name<Tr: 'static>(&self) -> Arc<str> {
+        self.types.get::<Tr>()
+    }
+
+    /// Names of the traces handed out since the last call, to tell which traces a peek reads.
+    pub(crate) fn take_reads(&self) -> BTreeSet<String> {
+        self.reads.take()
+    }
+
+    pub fn names(&self) -> BTreeSet<String> {
+        self.traces.keys().cloned().collect()
     }
 
     pub fn physical_compaction(&mut self) {
@@ -113,6 +246,25 @@ where
         }
     }
 
+    /// The upper of every trace, by name.
+    pub(crate) fn uppers(&mut self) -> BTreeMap<String, Antichain<T>> {
+        self.traces
+            .iter_mut()
+            .map(|(name, bundle)| (name.clone(), (bundle.upper_fn)(&mut bundle.trace)))
+            .collect()
+    }
+
+    /// The lower bound of all traces' uppers, empty if no trace holds back.
+    pub fn upper(&mut self) -> Antichain<T> {
+        let mut ret = Antichain::new();
+        for bundle in self.traces.values_mut() {
+            for time in (bundle.upper_fn)(&mut bundle.trace) {
+                ret.insert(time);
+            }
+        }
+        ret
+    }
+
     pub(crate) fn collect_info(&mut self) -> Vec<BundleInfo<T>> {
         let mut ret = vec![];
         for bundle in self.traces.values_mut() {
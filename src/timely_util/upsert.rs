@@ -0,0 +1,407 @@
+//! Arrange a stream of keyed upserts, like `differential_dataflow::operators::arrange::upsert`
+//! but for any abelian diff and with updates resolved against the current value.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+use differential_dataflow::difference::{IsZero, Semigroup};
+use differential_dataflow::logging::DifferentialEventBuilder;
+use differential_dataflow::operators::arrange::{Arranged, TraceAgent};
+use differential_dataflow::trace::cursor::IntoOwned;
+use differential_dataflow::trace::{
+    Batch, Builder, Cursor, Description, ExertionLogic, Trace, TraceReader,
+};
+use differential_dataflow::{ExchangeData, Hashable};
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::Operator;
+use timely::dataflow::operators::Capability;
+use timely::dataflow::{Scope, Stream};
+use timely::order::{PartialOrder, TotalOrder};
+use timely::progress::{Antichain, Timestamp};
+
+// an update waiting for its time to complete, ordered by time and then arrival
+struct Pending<T, K, P> {
+    time: T,
+    seq: u64,
+    key: K,
+    update: P,
+}
+
+impl<T: Ord, K, P> PartialEq for Pending<T, K, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord, K, P> Eq for Pending<T, K, P> {}
+
+impl<T: Ord, K, P> PartialOrd for Pending<T, K, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord, K, P> Ord for Pending<T, K, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.time, self.seq).cmp(&(&other.time, other.seq))
+    }
+}
+
+/// Each key holds at most one value, `merge` gives the new value from the current one and an
+/// update, `None` deletes the key. Updates at the same time are merged in the order they arrive.
+///
+/// A changed value is retracted with `-1` and the new one added with `1`, converted into
+/// `Tr::Diff`.
+pub(crate) fn arrange_from_merge<G, K, V, P, Tr, F>(
+    stream: &Stream<G, (K, P, G::Timestamp)>,
+    name: &str,
+    merge: F,
+) -> Arranged<G, TraceAgent<Tr>>
+where
+    G: Scope<Timestamp = Tr::Time>,
+    Tr: Trace + TraceReader + 'static,
+    Tr::Diff: Semigroup + ExchangeData + From<i8>,
+    for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = K>,
+    K: ExchangeData + Hashable + std::hash::Hash,
+    V: ExchangeData,
+    P: timely::ExchangeData,
+    F: Fn(Option<&V>, P) -> Option<V> + 'static,
+    for<'a> Tr::Val<'a>: IntoOwned<'a, Owned = V>,
+    Tr::Time: TotalOrder + ExchangeData,
+    Tr::Batch: Batch,
+    Tr::Builder: Builder<Input = Vec<((K, V), Tr::Time, Tr::Diff)>>,
+{
+    let mut reader: Option<TraceAgent<Tr>> = None;
+
+    let stream = {
+        let reader = &mut reader;
+        let exchange = Exchange::new(move |update: &(K, P, G::Timestamp)| update.0.hashed().into());
+
+        stream.unary_frontier(exchange, name, move |_capability, info| {
+            // lower envelope of the times in `queue`
+            let mut capabilities = Antichain::<Capability<G::Timestamp>>::new();
+            // log like the arrangements of differential, and exert as configured for them
+            let logger = stream
+                .scope()
+                .log_register()
+                .get::<DifferentialEventBuilder>("differential/arrange")
+                .map(Into::into);
+            let activator = Some(stream.scope().activator_for(info.address.clone()));
+            let mut empty_trace = Tr::new(info.clone(), logger.clone(), activator);
+            let exert_logic = stream
+                .scope()
+                .config()
+                .get::<ExertionLogic>("differential/default_exert_logic")
+                .cloned();
+            if let Some(exert_logic) = exert_logic {
+                empty_trace.set_exert_logic(exert_logic);
+            }
+            let (mut reader_local, mut writer) = TraceAgent::new(empty_trace, info, logger);
+            *reader = Some(reader_local.clone());
+
+            let mut prev_frontier = Antichain::from_elem(<G::Timestamp as Timestamp>::minimum());
+            // updates by increasing time, `BinaryHeap` is a max-heap
+            let mut queue = BinaryHeap::<Reverse<Pending<G::Timestamp, K, P>>>::new();
+            let mut seq = 0;
+            let mut updates = Vec::new();
+            let one = Tr::Diff::from(1);
+            let minus_one = Tr::Diff::from(-1);
+
+            move |input, output| {
+                input.for_each(|cap, data| {
+                    capabilities.insert(cap.retain());
+                    for (key, update, time) in data.drain(..) {
+                        queue.push(Reverse(Pending {
+                            time,
+                            seq,
+                            key,
+                            update,
+                        }));
+                        seq += 1;
+                    }
+                });
+
+                assert!(PartialOrder::less_equal(
+                    &prev_frontier.borrow(),
+                    &input.frontier().frontier()
+                ));
+
+                // only strict progress gives something to do
+                if prev_frontier.borrow() == input.frontier().frontier() {
+                    writer.exert();
+                    return;
+                }
+
+                if capabilities
+                    .elements()
+                    .iter()
+                    .any(|c| !input.frontier().less_equal(c.time()))
+                {
+                    let mut upper = Antichain::new();
+                    for (index, capability) in capabilities.elements().iter().enumerate() {
+                        if input.frontier().less_equal(capability.time()) {
+                            continue;
+                        }
+
+                        // retire the capabilities one by one, respecting the later ones
+                        upper.clear();
+                        for time in input.frontier().frontier().iter() {
+                            upper.insert(time.clone());
+                        }
+                        for other in &capabilities.elements()[(index + 1)..] {
+                            upper.insert(other.time().clone());
+                        }
+
+                        let mut to_process = HashMap::new();
+                        while queue
+                            .peek()
+                            .map(|Reverse(p)| !upper.less_equal(&p.time))
+                            .unwrap_or(false)
+                        {
+                            // popped in order, so each key's list is too
+                            let Reverse(p) = queue.pop().unwrap();
+                            to_process
+                                .entry(p.key)
+                                .or_insert_with(Vec::new)
+                                .push((p.time, p.update));
+                        }
+                        if queue.capacity() > 4 * queue.len() {
+                            queue.shrink_to_fit();
+                        }
+
+                        // key order, to match the cursor
+                        let mut to_process = to_process.into_iter().collect::<Vec<_>>();
+                        to_process.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+
+                        let (mut cursor, storage) = reader_local.cursor();
+                        let mut builder = Tr::Builder::new();
+                        for (key, list) in to_process {
+                            let mut prev_value: Option<V> = None;
+                            cursor.seek_key(&storage, IntoOwned::borrow_as(&key));
+                            if cursor
+                                .get_key(&storage)
+                                .map(|k| k.eq(&IntoOwned::borrow_as(&key)))
+                                .unwrap_or(false)
+                            {
+                                while let Some(val) = cursor.get_val(&storage) {
+                                    // no zero without a `Monoid`, `None` stands for it
+                                    let mut count: Option<Tr::Diff> = None;
+                                    cursor.map_times(&storage, |_time, diff| match &mut count {
+                                        Some(count) => count.plus_equals(&diff.into_owned()),
+                                        None => count = Some(diff.into_owned()),
+                                    });
+                                    let count = count.filter(|count| !count.is_zero());
+                                    assert!(count.is_none() || count.as_ref() == Some(&one));
+                                    if count.is_some() {
+                                        assert!(prev_value.is_none());
+                                        prev_value = Some(val.into_owned());
+                                    }
+                                    cursor.step_val(&storage);
+                                }
+                                cursor.step_key(&storage);
+                            }
+
+                            let mut list = list.into_iter().peekable();
+                            while let Some((time, update)) = list.next() {
+                                // updates at the same time apply in turn, only the result shows
+                                let mut next = merge(prev_value.as_ref(), update);
+                                while let Some((_, update)) = list.next_if(|(t, _)| *t == time) {
+                                    next = merge(next.as_ref(), update);
+                                }
+                                if prev_value != next {
+                                    if let Some(prev) = prev_value {
+                                        updates.push((
+                                            (key.clone(), prev),
+                                            time.clone(),
+                                            minus_one.clone(),
+                                        ));
+                                    }
+                                    if let Some(next) = next.as_ref() {
+                                        updates.push((
+                                            (key.clone(), next.clone()),
+                                            time.clone(),
+                                            one.clone(),
+                                        ));
+                                    }
+                                    prev_value = next;
+                                }
+                            }
+                            updates.sort();
+                            builder.push(&mut updates);
+                        }
+                        let description = Description::new(
+                            prev_frontier.clone(),
+                            upper.clone(),
+                            Antichain::from_elem(G::Timestamp::minimum()),
+                        );
+                        let batch = builder.done(description);
+                        prev_frontier.clone_from(&upper);
+
+                        writer.insert(batch.clone(), Some(capability.time().clone()));
+                        output.session(&capabilities.elements()[index]).give(batch);
+                    }
+
+                    // keep a capability for the earliest queued update only
+                    let mut new_capabilities = Antichain::new();
+                    if let Some(Reverse(Pending { time, .. })) = queue.peek() {
+                        let capability = capabilities
+                            .elements()
+                            .iter()
+                            .find(|c| c.time().less_equal(time))
+                            .expect("failed to find capability");
+                        new_capabilities.insert(capability.delayed(time));
+                    }
+                    capabilities = new_capabilities;
+                } else {
+                    // announce progress, even without data
+                    writer.seal(input.frontier().frontier().to_owned());
+                }
+
+                prev_frontier.clear();
+                prev_frontier.extend(input.frontier().frontier().iter().cloned());
+                reader_local.set_logical_compaction(prev_frontier.borrow());
+                reader_local.set_physical_compaction(prev_frontier.borrow());
+
+                writer.exert();
+            }
+        })
+    };
+
+    Arranged {
+        stream,
+        trace: reader.unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use differential_dataflow::trace::implementations::ord_neu::OrdValSpine;
+    use timely::dataflow::operators::Input;
+
+    use super::*;
+
+    type Spine<V> = TraceAgent<OrdValSpine<u64, V, u64, i64>>;
+
+    /// Arrange `updates` of `(key, patch, time)` merged by `merge`, and read back every update.
+    fn arrange<V, P>(
+        updates: Vec<(u64, P, u64)>,
+        merge: fn(Option<&V>, P) -> Option<V>,
+    ) -> Vec<((u64, V), u64, i64)>
+    where
+        V: ExchangeData,
+        P: timely::ExchangeData,
+    {
+        timely::execute_directly(move |worker| {
+            let (mut input, mut trace) = worker.dataflow::<u64, _, _>(|scope| {
+                let (input, stream) = scope.new_input::<(u64, P, u64)>();
+                let arranged =
+                    arrange_from_merge::<_, u64, V, P, Spine<V>, _>(&stream, "Upsert", merge);
+                (input, arranged.trace)
+            });
+            for update in updates {
+                input.send(update);
+            }
+            let end = 100;
+            input.advance_to(end);
+            let mut upper = Antichain::new();
+            loop {
+                worker.step();
+                trace.read_upper(&mut upper);
+                if !upper.less_than(&end) {
+                    break;
+                }
+            }
+
+            let mut ret = vec![];
+            let (mut cursor, storage) = trace.cursor();
+            while let Some(key) = cursor.get_key(&storage) {
+                while let Some(val) = cursor.get_val(&storage) {
+                    cursor.map_times(&storage, |t, diff| {
+                        let record = (key.into_owned(), val.into_owned());
+                        ret.push((record, t.into_owned(), diff.into_owned()));
+                    });
+                    cursor.step_val(&storage);
+                }
+                cursor.step_key(&storage);
+            }
+            ret.sort();
+            ret
+        })
+    }
+
+    fn replace(_old: Option<&String>, new: Option<String>) -> Option<String> {
+        new
+    }
+
+    fn add(old: Option<&i64>, patch: i64) -> Option<i64> {
+        let sum = old.copied().unwrap_or(0) + patch;
+        (sum != 0).then_some(sum)
+    }
+
+    fn s(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn test_replace() {
+        let updates = vec![(1, s("a"), 1), (2, s("b"), 1), (1, s("c"), 2)];
+        let expected = vec![
+            ((1, "a".to_string()), 1, 1),
+            ((1, "a".to_string()), 2, -1),
+            ((1, "c".to_string()), 2, 1),
+            ((2, "b".to_string()), 1, 1),
+        ];
+        assert_eq!(arrange(updates, replace), expected);
+    }
+
+    #[test]
+    fn test_delete() {
+        let updates = vec![(1, s("a"), 1), (1, None, 2), (2, None, 3), (1, s("a"), 4)];
+        let expected = vec![
+            ((1, "a".to_string()), 1, 1),
+            ((1, "a".to_string()), 2, -1),
+            ((1, "a".to_string()), 4, 1),
+        ];
+        // deleting a missing key changes nothing
+        assert_eq!(arrange(updates, replace), expected);
+    }
+
+    #[test]
+    fn test_same_time() {
+        // only the result of the updates at one time shows
+        let updates = vec![
+            (1, s("a"), 1),
+            (1, s("b"), 1),
+            (1, s("c"), 1),
+            (2, s("d"), 1),
+        ];
+        let expected = vec![((1, "c".to_string()), 1, 1), ((2, "d".to_string()), 1, 1)];
+        assert_eq!(arrange(updates, replace), expected);
+
+        // a key set and deleted at one time never shows
+        let updates = vec![(1, s("a"), 1), (1, None, 1)];
+        assert_eq!(arrange(updates, replace), vec![]);
+
+        // updates at one time merge in the order they are sent, regardless of later ones
+        let updates = vec![(1, 2, 2), (1, 3, 1), (1, 4, 2)];
+        let expected = vec![((1, 3), 1, 1), ((1, 3), 2, -1), ((1, 9), 2, 1)];
+        assert_eq!(arrange(updates, add), expected);
+    }
+
+    #[test]
+    fn test_counts() {
+        // every value is counted once, a change retracts the old one with -1
+        let updates = vec![(1, 5, 1), (1, -2, 2), (1, -3, 3), (1, 1, 4)];
+        let expected = vec![
+            ((1, 1), 4, 1),
+            ((1, 3), 2, 1),
+            ((1, 3), 3, -1),
+            ((1, 5), 1, 1),
+            ((1, 5), 2, -1),
+        ];
+        let ret = arrange(updates, add);
+        assert_eq!(ret, expected);
+        let total: i64 = ret.iter().map(|(_, _, diff)| diff).sum();
+        assert_eq!(total, 1);
+    }
+}
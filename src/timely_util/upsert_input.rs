@@ -1,40 +1,99 @@
-use std::any::{type_name, Any, TypeId};
+use std::any::{type_name, Any};
 use std::collections::BTreeMap;
+use std::fmt;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use differential_dataflow::difference::Semigroup;
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::arrange::{upsert, Arranged, TraceAgent};
+use differential_dataflow::operators::arrange::{Arranged, TraceAgent};
 use differential_dataflow::trace::cursor::IntoOwned;
 use differential_dataflow::trace::implementations::ord_neu::OrdValSpine;
 use differential_dataflow::trace::{Batch, Builder, Trace, TraceReader};
-use differential_dataflow::AsCollection;
 use differential_dataflow::{Collection, ExchangeData, Hashable};
-use timely::dataflow::operators::{Input, Map};
+use timely::dataflow::operators::Input;
 use timely::dataflow::{InputHandle, Scope};
 use timely::order::TotalOrder;
 use timely::progress::Timestamp;
 
+use crate::timely_util::trace_group::TraceGroup;
+use crate::timely_util::{upsert, TypeNames};
+use crate::Error;
+
+/// The arrangement behind an upsert input, `U` keyed by `U::Key`.
+pub type UpsertTrace<U, T, R> = TraceAgent<OrdValSpine<<U as UpsertInput>::Key, U, T, R>>;
+
 pub trait UpsertInput {
     type Key;
 
     fn get_key(&self) -> Self::Key;
 }
 
+/// An upsert input updated by patches, see `UpsertInputGroup::alloc_merge_collection`.
+///
+/// Patches are merged inside the dataflow, so clients patching the same key do not race.
+pub trait UpsertMerge: UpsertInput + Sized {
+    type Patch;
+
+    /// The key's new value, `None` deletes the key.
+    fn merge(old: Option<&Self>, patch: Self::Patch) -> Option<Self>;
+}
+
+// the merge of plain upsert inputs
+fn replace<U>(_old: Option<&U>, new: Option<U>) -> Option<U> {
+    new
+}
+
 struct Bundle<T> {
-    name: &'static str,
     handle: Box<dyn Any>,
     advance_fn: Box<dyn Fn(&mut Box<dyn Any>, T)>,
     get_time_fn: Box<dyn Fn(&mut Box<dyn Any>) -> T>,
 }
 
 pub(crate) struct BundleInfo<T> {
-    pub(crate) name: &'static str,
+    pub(crate) name: String,
     pub(crate) time: T,
 }
 
+/// Names an upsert input of `U` registered in an `UpsertInputGroup`.
+pub struct UpsertRef<U> {
+    name: Arc<str>,
+    _marker: PhantomData<fn() -> U>,
+}
+
+impl<U> UpsertRef<U> {
+    /// Refs are handed out by `register` and the `alloc_*` methods.
+    pub(crate) fn new(name: impl Into<Arc<str>>) -> Self {
+        UpsertRef {
+            name: name.into(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<U> Clone for UpsertRef<U> {
+    fn clone(&self) -> Self {
+        UpsertRef::new(self.name.clone())
+    }
+}
+
+impl<U> fmt::Debug for UpsertRef<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UpsertRef({})", self.name)
+    }
+}
+
+/// Upsert inputs by name, the type-keyed methods find an input by the type of `U`.
 pub struct UpsertInputGroup<T, R> {
-    inputs: BTreeMap<TypeId, Bundle<T>>,
+    inputs: BTreeMap<String, Bundle<T>>,
+    // names of the inputs registered by type
+    types: TypeNames,
+    // arranged inputs by the input's name, dataflows installed later import them
+    shared: TraceGroup<T>,
     _marker: PhantomData<R>,
 }
 
@@ -46,75 +105,187 @@ where
     pub fn new() -> Self {
         UpsertInputGroup {
             inputs: BTreeMap::new(),
+            types: TypeNames::new(),
+            shared: TraceGroup::new(),
             _marker: PhantomData,
         }
     }
 
-    pub fn register<U>(&mut self, handle: InputHandle<T, (U::Key, Option<U>, T)>)
+    pub fn register<U>(&mut self, handle: InputHandle<T, (U::Key, Option<U>, T)>) -> UpsertRef<U>
     where
         U::Key: Clone + 'static,
         U: UpsertInput + Clone + 'static,
     {
-        let tid = TypeId::of::<U>();
-        let name = type_name::<U>();
+        let name = self.type_keyed_name::<U>();
+        self.register_named(name, handle)
+    }
+
+    /// Register `handle` under `name`, so several inputs of the same type can be registered.
+    pub fn register_named<U>(
+        &mut self,
+        name: impl Into<String>,
+        handle: InputHandle<T, (U::Key, Option<U>, T)>,
+    ) -> UpsertRef<U>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.insert_input(name.into(), handle)
+    }
+
+    /// Name the type-keyed input of `U` after its type.
+    fn type_keyed_name<U: 'static>(&mut self) -> String {
+        let inputs = &self.inputs;
+        self.types.insert::<U>(
+            type_name::<U>(),
+            |name| inputs.contains_key(name),
+            "InputHandle",
+        )
+    }
+
+    fn insert_input<U, D>(&mut self, name: String, handle: InputHandle<T, D>) -> UpsertRef<U>
+    where
+        D: Clone + 'static,
+    {
         let handle = Box::new(handle);
         let advance_fn = Box::new(|any: &mut Box<dyn Any>, t: T| {
-            let handle: &mut InputHandle<T, (U::Key, Option<U>, T)> = any.downcast_mut().unwrap();
+            let handle: &mut InputHandle<T, D> = any.downcast_mut().unwrap();
             handle.advance_to(t);
         });
         let get_time_fn = Box::new(|any: &mut Box<dyn Any>| {
-            let handle: &mut InputHandle<T, (U::Key, Option<U>, T)> = any.downcast_mut().unwrap();
+            let handle: &mut InputHandle<T, D> = any.downcast_mut().unwrap();
             handle.time().clone()
         });
         let bundle = Bundle {
-            name,
             handle,
             advance_fn,
             get_time_fn,
         };
-        let d = self.inputs.insert(tid, bundle);
-        assert!(d.is_none(), "register same InputHandle");
+        let d = self.inputs.insert(name.clone(), bundle);
+        assert!(d.is_none(), "register same InputHandle: {name}");
+        UpsertRef::new(name)
     }
 
-    pub fn get<U>(&self) -> Option<&InputHandle<T, (U::Key, Option<U>, T)>>
+    pub fn get<U>(&self) -> Result<&InputHandle<T, (U::Key, Option<U>, T)>, Error>
     where
         U::Key: Clone + 'static,
         U: UpsertInput + Clone + 'static,
     {
-        let tid = TypeId::of::<U>();
-        let bundle = self.inputs.get(&tid)?;
-        Some(bundle.handle.downcast_ref().unwrap())
+        self.get_named(&self.types.get::<U>())
     }
 
-    pub fn get_mut<U>(&mut self) -> Option<&mut InputHandle<T, (U::Key, Option<U>, T)>>
+    pub fn get_mut<U>(&mut self) -> Result<&mut InputHandle<T, (U::Key, Option<U>, T)>, Error>
     where
         U::Key: Clone + 'static,
         U: UpsertInput + Clone + 'static,
     {
-        let tid = TypeId::of::<U>();
-        let bundle = self.inputs.get_mut(&tid)?;
-        Some(bundle.handle.downcast_mut().unwrap())
+        let name = self.types.get::<U>();
+        self.get_named_mut(&name)
+    }
+
+    /// Fails if no input is registered under `name` or it does not take `U`.
+    pub fn get_named<U>(&self, name: &str) -> Result<&InputHandle<T, (U::Key, Option<U>, T)>, Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        let input = self.inputs.get(name).and_then(|b| b.handle.downcast_ref());
+        input.ok_or_else(|| self.not_registered(name, type_name::<U>()))
+    }
+
+    pub fn get_named_mut<U>(
+        &mut self,
+        name: &str,
+    ) -> Result<&mut InputHandle<T, (U::Key, Option<U>, T)>, Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.handle_mut(name, type_name::<U>())
+    }
+
+    fn handle_mut<D: 'static>(
+        &mut self,
+        name: &str,
+        type_name: &'static str,
+    ) -> Result<&mut InputHandle<T, D>, Error> {
+        let found = self
+            .inputs
+            .get(name)
+            .is_some_and(|b| b.handle.is::<InputHandle<T, D>>());
+        if !found {
+            return Err(self.not_registered(name, type_name));
+        }
+        Ok(self
+            .inputs
+            .get_mut(name)
+            .unwrap()
+            .handle
+            .downcast_mut()
+            .unwrap())
+    }
+
+    fn not_registered(&self, name: &str, type_name: &'static str) -> Error {
+        Error::NotRegistered {
+            name: name.to_string(),
+            type_name,
+            registered: self.inputs.keys().cloned().collect(),
+        }
+    }
+
+    pub fn input<U>(
+        &self,
+        input: &UpsertRef<U>,
+    ) -> Result<&InputHandle<T, (U::Key, Option<U>, T)>, Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.get_named(input.name())
+    }
+
+    pub fn input_mut<U>(
+        &mut self,
+        input: &UpsertRef<U>,
+    ) -> Result<&mut InputHandle<T, (U::Key, Option<U>, T)>, Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.get_named_mut(input.name())
     }
 
     pub fn upsert<U>(&mut self, value: U)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_upsert(value).unwrap()
+    }
+
+    pub fn try_upsert<U>(&mut self, value: U) -> Result<(), Error>
     where
         U::Key: Clone + 'static,
         U: UpsertInput + Clone + 'static,
     {
         let key = value.get_key();
-        let handle = self.get_mut::<U>().expect("not registered");
+        let handle = self.get_mut::<U>()?;
         let time = handle.time();
         handle.send((key, Some(value), time.clone()));
+        Ok(())
     }
 
-    fn get_arrange_named<U, Tr, G>(
+    fn get_arrange_named<U, P, Tr, G>(
         &mut self,
+        input: &str,
         scope: &mut G,
         name: &str,
-    ) -> Option<Arranged<G, TraceAgent<Tr>>>
+        merge: impl Fn(Option<&U>, P) -> Option<U> + 'static,
+    ) -> Result<Arranged<G, TraceAgent<Tr>>, Error>
     where
         G: Scope<Timestamp = T>,
-        Tr: Trace + TraceReader<Time = T, Diff = isize> + 'static,
+        P: timely::ExchangeData,
+        Tr: Trace + TraceReader<Time = T, Diff = R> + 'static,
         for<'a> Tr::Key<'a>: IntoOwned<'a, Owned = U::Key>,
         U::Key: ExchangeData + Hashable + std::hash::Hash,
         U: UpsertInput + ExchangeData,
@@ -122,57 +293,493 @@ where
         T: TotalOrder + ExchangeData + Lattice,
         Tr::Batch: Batch,
         Tr::Builder: Builder<Input = Vec<((U::Key, U), Tr::Time, Tr::Diff)>>,
+        R: Semigroup + ExchangeData + From<i8>,
     {
-        let input = self.get_mut::<U>()?;
+        let input = self.handle_mut::<(U::Key, P, T)>(input, type_name::<U>())?;
         let stream = scope.input_from(input);
-        Some(upsert::arrange_from_upsert::<G, U::Key, U, Tr>(
-            &stream, name,
+        Ok(upsert::arrange_from_merge::<G, U::Key, U, P, Tr, _>(
+            &stream, name, merge,
         ))
     }
 
-    pub fn alloc_collection<U, G>(&mut self, scope: &mut G) -> Collection<G, U, R>
+    /// The returned ref finds the input again, e.g. in `App::handle_update`.
+    pub fn alloc_collection<U, G>(&mut self, scope: &mut G) -> (Collection<G, U, R>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.type_keyed_name::<U>();
+        self.alloc_collection_named(name, scope)
+    }
+
+    pub fn alloc_collection_named<U, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+    ) -> (Collection<G, U, R>, UpsertRef<U>)
     where
         G: Scope<Timestamp = T>,
         U::Key: ExchangeData + Hashable + std::hash::Hash,
         U: UpsertInput + ExchangeData,
         T: TotalOrder + ExchangeData + Lattice,
-        R: From<i64>,
+        R: Semigroup + ExchangeData + From<i8>,
     {
         let input: InputHandle<T, (U::Key, Option<U>, T)> = InputHandle::new();
-        self.register(input);
-        self.get_collection(scope).unwrap()
+        let input = self.register_named(name, input);
+        let collection = self.get_collection(input.name(), scope).unwrap();
+        (collection, input)
+    }
+
+    fn get_collection<U, G>(
+        &mut self,
+        input: &str,
+        scope: &mut G,
+    ) -> Result<Collection<G, U, R>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let arranged = self.get_arranged::<U, _, G>(input, scope, replace)?;
+        Ok(arranged.as_collection(|_k, v| v.clone()))
     }
 
-    fn get_collection<U, G>(&mut self, scope: &mut G) -> Option<Collection<G, U, R>>
+    fn get_arranged<U, P, G>(
+        &mut self,
+        input: &str,
+        scope: &mut G,
+        merge: impl Fn(Option<&U>, P) -> Option<U> + 'static,
+    ) -> Result<Arranged<G, UpsertTrace<U, T, R>>, Error>
     where
         G: Scope<Timestamp = T>,
+        P: timely::ExchangeData,
         U::Key: ExchangeData + Hashable + std::hash::Hash,
         U: UpsertInput + ExchangeData,
         T: TotalOrder + ExchangeData + Lattice,
-        R: From<i64>,
-    {
-        let arranged = self
-            .get_arrange_named::<U, OrdValSpine<_, _, _, _>, G>(scope, "UpsertInputToCollection")?;
-        let collection = arranged.as_collection(|_k, v| v.clone());
-        Some(
-            collection
-                .inner
-                .map(|(u, t, r)| {
-                    let d: i64 = r.try_into().expect("failed to convert isize diff to i64");
-                    (u, t, d.into())
-                })
-                .as_collection(),
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        self.get_arrange_named::<U, P, OrdValSpine<_, _, _, _>, G>(
+            input,
+            scope,
+            "UpsertInputToCollection",
+            merge,
         )
     }
 
+    /// Keep the arrangement of `input`, so dataflows installed later can import it.
+    fn share<U, G>(&mut self, input: &UpsertRef<U>, arranged: &Arranged<G, UpsertTrace<U, T, R>>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        self.shared
+            .register_trace_named(input.name(), arranged.trace.clone());
+    }
+
+    /// Like `alloc_collection`, but keep the arrangement so views can import the input.
+    pub fn alloc_shared_collection<U, G>(
+        &mut self,
+        scope: &mut G,
+    ) -> (Collection<G, U, R>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.type_keyed_name::<U>();
+        self.alloc_shared_collection_named(name, scope)
+    }
+
+    pub fn alloc_shared_collection_named<U, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+    ) -> (Collection<G, U, R>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let input = self.register_named::<U>(name, InputHandle::new());
+        let arranged = self.get_arranged(input.name(), scope, replace).unwrap();
+        self.share(&input, &arranged);
+        (arranged.as_collection(|_k, v| v.clone()), input)
+    }
+
+    /// Like `alloc_collection`, but keep the arrangement by `U::Key` and register its trace in
+    /// `traces`, so queries can look up the current value of a key. Views can import the input.
+    pub fn alloc_arranged<U, G>(
+        &mut self,
+        scope: &mut G,
+        traces: &mut TraceGroup<T>,
+    ) -> (Arranged<G, UpsertTrace<U, T, R>>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let input = self.register::<U>(InputHandle::new());
+        let arranged = self.get_arranged(input.name(), scope, replace).unwrap();
+        traces.register_trace(arranged.trace.clone());
+        self.share(&input, &arranged);
+        (arranged, input)
+    }
+
+    /// Like `alloc_arranged`, the trace is registered as `name` too.
+    pub fn alloc_arranged_named<U, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+        traces: &mut TraceGroup<T>,
+    ) -> (Arranged<G, UpsertTrace<U, T, R>>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let input = self.register_named::<U>(name, InputHandle::new());
+        let arranged = self.get_arranged(input.name(), scope, replace).unwrap();
+        traces.register_trace_named(input.name(), arranged.trace.clone());
+        self.share(&input, &arranged);
+        (arranged, input)
+    }
+
+    /// Like `alloc_collection`, but the input takes patches of `U` instead, see `patch`.
+    pub fn alloc_merge_collection<U, G>(
+        &mut self,
+        scope: &mut G,
+    ) -> (Collection<G, U, R>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertMerge + ExchangeData,
+        U::Patch: timely::ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.type_keyed_name::<U>();
+        self.alloc_merge_collection_named(name, scope)
+    }
+
+    pub fn alloc_merge_collection_named<U, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+    ) -> (Collection<G, U, R>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertMerge + ExchangeData,
+        U::Patch: timely::ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let input = self.register_merge::<U>(name.into());
+        let arranged = self.get_arranged(input.name(), scope, U::merge).unwrap();
+        (arranged.as_collection(|_k, v| v.clone()), input)
+    }
+
+    /// Like `alloc_arranged`, but the input takes patches of `U` instead, see `patch`.
+    pub fn alloc_merge_arranged<U, G>(
+        &mut self,
+        scope: &mut G,
+        traces: &mut TraceGroup<T>,
+    ) -> (Arranged<G, UpsertTrace<U, T, R>>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertMerge + ExchangeData,
+        U::Patch: timely::ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.type_keyed_name::<U>();
+        let input = self.register_merge::<U>(name);
+        let arranged = self.get_arranged(input.name(), scope, U::merge).unwrap();
+        traces.register_trace(arranged.trace.clone());
+        self.share(&input, &arranged);
+        (arranged, input)
+    }
+
+    /// Like `alloc_merge_arranged`, the trace is registered as `name` too.
+    pub fn alloc_merge_arranged_named<U, G>(
+        &mut self,
+        name: impl Into<String>,
+        scope: &mut G,
+        traces: &mut TraceGroup<T>,
+    ) -> (Arranged<G, UpsertTrace<U, T, R>>, UpsertRef<U>)
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertMerge + ExchangeData,
+        U::Patch: timely::ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let input = self.register_merge::<U>(name.into());
+        let arranged = self.get_arranged(input.name(), scope, U::merge).unwrap();
+        traces.register_trace_named(input.name(), arranged.trace.clone());
+        self.share(&input, &arranged);
+        (arranged, input)
+    }
+
+    fn register_merge<U>(&mut self, name: String) -> UpsertRef<U>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertMerge + 'static,
+        U::Patch: Clone + 'static,
+    {
+        let handle: InputHandle<T, (U::Key, U::Patch, T)> = InputHandle::new();
+        self.insert_input(name, handle)
+    }
+
+    /// Import an input allocated by `alloc_shared_collection` or an `alloc_*arranged` method into
+    /// another dataflow.
+    pub fn import_collection<U, G>(&mut self, scope: &mut G) -> Result<Collection<G, U, R>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.types.get::<U>();
+        self.import_collection_named(&name, scope)
+    }
+
+    pub fn import_collection_named<U, G>(
+        &mut self,
+        name: &str,
+        scope: &mut G,
+    ) -> Result<Collection<G, U, R>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        Ok(self
+            .import_arranged_named::<U, G>(name, scope)?
+            .as_collection(|_k, v| v.clone()))
+    }
+
+    /// Like `import_collection`, but keep the arrangement by `U::Key`, e.g. to join against it.
+    pub fn import_arranged<U, G>(
+        &mut self,
+        scope: &mut G,
+    ) -> Result<Arranged<G, UpsertTrace<U, T, R>>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let name = self.types.get::<U>();
+        self.import_arranged_named(&name, scope)
+    }
+
+    pub fn import_arranged_named<U, G>(
+        &mut self,
+        name: &str,
+        scope: &mut G,
+    ) -> Result<Arranged<G, UpsertTrace<U, T, R>>, Error>
+    where
+        G: Scope<Timestamp = T>,
+        U::Key: ExchangeData + Hashable + std::hash::Hash,
+        U: UpsertInput + ExchangeData,
+        T: TotalOrder + ExchangeData + Lattice,
+        R: Semigroup + ExchangeData + From<i8>,
+    {
+        let trace = self.shared.get_named_mut::<UpsertTrace<U, T, R>>(name)?;
+        Ok(trace.import(scope))
+    }
+
+    pub(crate) fn logical_compaction(&mut self, frontier: T) {
+        self.shared.logical_compaction(frontier);
+    }
+
+    pub(crate) fn physical_compaction(&mut self) {
+        self.shared.physical_compaction();
+    }
+
     pub fn delete<U>(&mut self, key: U::Key)
     where
         U::Key: Clone + 'static,
         U: UpsertInput + Clone + 'static,
     {
-        let handle = self.get_mut::<U>().expect("not registered");
+        self.try_delete::<U>(key).unwrap()
+    }
+
+    pub fn try_delete<U>(&mut self, key: U::Key) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        let handle = self.get_mut::<U>()?;
         let time = handle.time();
         handle.send((key, None, time.clone()));
+        Ok(())
+    }
+
+    pub fn upsert_batch<U>(&mut self, values: impl IntoIterator<Item = U>)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_upsert_batch(values).unwrap()
+    }
+
+    pub fn try_upsert_batch<U>(&mut self, values: impl IntoIterator<Item = U>) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_apply(
+            values
+                .into_iter()
+                .map(|value| (value.get_key(), Some(value))),
+        )
+    }
+
+    pub fn delete_batch<U>(&mut self, keys: impl IntoIterator<Item = U::Key>)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_delete_batch::<U>(keys).unwrap()
+    }
+
+    pub fn try_delete_batch<U>(
+        &mut self,
+        keys: impl IntoIterator<Item = U::Key>,
+    ) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_apply::<U>(keys.into_iter().map(|key| (key, None)))
+    }
+
+    /// Upsert or delete (`None`) many keys, sent to the input as one batch.
+    pub fn apply<U>(&mut self, updates: impl IntoIterator<Item = (U::Key, Option<U>)>)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_apply(updates).unwrap()
+    }
+
+    pub fn try_apply<U>(
+        &mut self,
+        updates: impl IntoIterator<Item = (U::Key, Option<U>)>,
+    ) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_apply_to(&UpsertRef::new(self.types.get::<U>()), updates)
+    }
+
+    /// Like `apply`, for the input `input` refers to.
+    pub fn apply_to<U>(
+        &mut self,
+        input: &UpsertRef<U>,
+        updates: impl IntoIterator<Item = (U::Key, Option<U>)>,
+    ) where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        self.try_apply_to(input, updates).unwrap()
+    }
+
+    pub fn try_apply_to<U>(
+        &mut self,
+        input: &UpsertRef<U>,
+        updates: impl IntoIterator<Item = (U::Key, Option<U>)>,
+    ) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertInput + Clone + 'static,
+    {
+        let handle = self.input_mut(input)?;
+        let time = handle.time().clone();
+        let mut batch: Vec<_> = updates
+            .into_iter()
+            .map(|(key, value)| (key, value, time.clone()))
+            .collect();
+        handle.send_batch(&mut batch);
+        Ok(())
+    }
+
+    /// Patch the value of `key`, the input must be allocated by `alloc_merge_collection`.
+    pub fn patch<U>(&mut self, key: U::Key, patch: U::Patch)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertMerge + 'static,
+        U::Patch: Clone + 'static,
+    {
+        self.patch_batch::<U>([(key, patch)])
+    }
+
+    pub fn patch_batch<U>(&mut self, patches: impl IntoIterator<Item = (U::Key, U::Patch)>)
+    where
+        U::Key: Clone + 'static,
+        U: UpsertMerge + 'static,
+        U::Patch: Clone + 'static,
+    {
+        self.patch_to(&UpsertRef::new(self.types.get::<U>()), patches)
+    }
+
+    /// Like `patch_batch`, for the input `input` refers to. Patches of a key sent by one worker
+    /// are merged in the order they are sent.
+    pub fn patch_to<U>(
+        &mut self,
+        input: &UpsertRef<U>,
+        patches: impl IntoIterator<Item = (U::Key, U::Patch)>,
+    ) where
+        U::Key: Clone + 'static,
+        U: UpsertMerge + 'static,
+        U::Patch: Clone + 'static,
+    {
+        self.try_patch_to(input, patches).unwrap()
+    }
+
+    pub fn try_patch_to<U>(
+        &mut self,
+        input: &UpsertRef<U>,
+        patches: impl IntoIterator<Item = (U::Key, U::Patch)>,
+    ) -> Result<(), Error>
+    where
+        U::Key: Clone + 'static,
+        U: UpsertMerge + 'static,
+        U::Patch: Clone + 'static,
+    {
+        let handle = self.handle_mut::<(U::Key, U::Patch, T)>(input.name(), type_name::<U>())?;
+        let time = handle.time().clone();
+        let mut batch: Vec<_> = patches
+            .into_iter()
+            .map(|(key, patch)| (key, patch, time.clone()))
+            .collect();
+        handle.send_batch(&mut batch);
+        Ok(())
     }
 
     pub fn advance_to(&mut self, frontier: T) {
@@ -183,13 +790,199 @@ where
 
     pub(crate) fn collect_info(&mut self) -> Vec<BundleInfo<T>> {
         let mut ret = vec![];
-        for bundle in self.inputs.values_mut() {
+        for (name, bundle) in self.inputs.iter_mut() {
             let time = (bundle.get_time_fn)(&mut bundle.handle);
             ret.push(BundleInfo {
-                name: bundle.name,
+                name: name.clone(),
                 time,
             });
         }
         ret
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use differential_dataflow::trace::Cursor;
+    use serde::{Deserialize, Serialize};
+    use timely::communication::allocator::Thread;
+    use timely::progress::Antichain;
+    use timely::worker::Worker;
+
+    use super::*;
+    use crate::testing::{item, read_items, Item, ItemTrace};
+    use crate::{SysDiff, SysTime};
+
+    /// Step `worker` until `trace` is complete up to `time`.
+    fn step_past<Tr>(worker: &mut Worker<Thread>, trace: &mut Tr, time: u64)
+    where
+        Tr: TraceReader<Time = SysTime>,
+    {
+        let time = SysTime::from(time);
+        let mut upper = Antichain::new();
+        loop {
+            worker.step();
+            trace.read_upper(&mut upper);
+            if !upper.less_equal(&time) {
+                return;
+            }
+        }
+    }
+
+    // a single count, patched by adding to it, deleted at zero
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    struct Counter(i64);
+
+    impl UpsertInput for Counter {
+        type Key = ();
+
+        fn get_key(&self) {}
+    }
+
+    impl UpsertMerge for Counter {
+        type Patch = i64;
+
+        fn merge(old: Option<&Self>, patch: i64) -> Option<Self> {
+            let count = old.map_or(0, |c| c.0) + patch;
+            (count != 0).then_some(Counter(count))
+        }
+    }
+
+    #[test]
+    fn test_try_updates() {
+        let mut group = UpsertInputGroup::<SysTime, SysDiff>::new();
+        let err = group.try_upsert(item(1, 10)).unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+        assert!(group.try_delete::<Item>(1).is_err());
+        let err = group.try_upsert_batch([item(1, 10)]).unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+        let err = group.try_delete_batch::<Item>([1, 2]).unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+        let err = group.try_apply([(1, Some(item(1, 10)))]).unwrap_err();
+        assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+
+        let items = group.register_named::<Item>("items", InputHandle::new());
+        group
+            .try_apply_to(&items, [(1, Some(item(1, 10))), (2, None)])
+            .unwrap();
+        // the type-keyed methods do not find a named input
+        assert!(group.try_upsert(item(1, 10)).is_err());
+
+        let missing = UpsertRef::<Item>::new("missing");
+        assert!(group.try_apply_to(&missing, [(1, None)]).is_err());
+        // a plain input can not be patched
+        let counters = UpsertRef::<Counter>::new("items");
+        assert!(group.try_patch_to(&counters, [((), 1)]).is_err());
+
+        let counters = group.register_merge::<Counter>("counters".to_string());
+        group.try_patch_to(&counters, [((), 1), ((), -1)]).unwrap();
+    }
+
+    #[test]
+    fn test_import_shared() {
+        timely::execute_directly(|worker| {
+            let mut group = UpsertInputGroup::<SysTime, SysDiff>::new();
+            let mut traces = TraceGroup::new();
+            worker.dataflow::<SysTime, _, _>(|scope| {
+                group.alloc_collection_named::<Item, _>("private", scope);
+                group.alloc_shared_collection_named::<Item, _>("shared", scope);
+                group.alloc_arranged_named::<Item, _>("arranged", scope, &mut traces);
+            });
+            worker.dataflow::<SysTime, _, _>(|scope| {
+                // plain collections keep no arrangement for other dataflows
+                let err = group
+                    .import_collection_named::<Item, _>("private", scope)
+                    .unwrap_err();
+                assert!(matches!(err, Error::NotRegistered { .. }), "{err}");
+                group
+                    .import_collection_named::<Item, _>("shared", scope)
+                    .unwrap();
+                group
+                    .import_arranged_named::<Item, _>("arranged", scope)
+                    .unwrap();
+            });
+        });
+    }
+
+    #[test]
+    fn test_alloc_arranged() {
+        timely::execute_directly(|worker| {
+            let mut group = UpsertInputGroup::<SysTime, SysDiff>::new();
+            let mut traces = TraceGroup::new();
+            let (other, input) = worker.dataflow(|scope| {
+                group.alloc_arranged::<Item, _>(scope, &mut traces);
+                let (other, input) =
+                    group.alloc_arranged_named::<Item, _>("other", scope, &mut traces);
+                assert_eq!(input.name(), "other");
+                (other.trace, input)
+            });
+            group.upsert_batch([item(7, 70), item(8, 80)]);
+            group.apply_to(&input, [(7, Some(item(7, 71)))]);
+            group.advance_to(1.into());
+
+            let mut trace = traces.get::<ItemTrace>().unwrap().clone();
+            step_past(worker, &mut trace, 0);
+            // a point lookup on the current value, without another dataflow
+            assert_eq!(read_items(&mut trace, 0.into(), Some(7)), vec![item(7, 70)]);
+            assert_eq!(read_items(&mut trace, 0.into(), Some(9)), vec![]);
+
+            let mut trace = traces.get_named::<ItemTrace>("other").unwrap().clone();
+            step_past(worker, &mut trace, 0);
+            assert_eq!(read_items(&mut trace, 0.into(), None), vec![item(7, 71)]);
+            // the returned arrangement reads the registered trace
+            let mut other = other;
+            assert_eq!(read_items(&mut other, 0.into(), None), vec![item(7, 71)]);
+        });
+    }
+
+    /// The current count of `trace`, at `time`.
+    fn read_count(trace: &mut UpsertTrace<Counter, SysTime, SysDiff>, time: u64) -> Option<i64> {
+        let time = SysTime::from(time);
+        let mut ret = None;
+        let (mut cursor, storage) = trace.cursor();
+        while let Some(val) = cursor.get_val(&storage) {
+            let mut count: SysDiff = 0;
+            cursor.map_times(&storage, |t, diff| {
+                if t.into_owned() <= time {
+                    count += diff.into_owned();
+                }
+            });
+            if count == 1 {
+                ret = Some(val.into_owned().0);
+            }
+            cursor.step_val(&storage);
+        }
+        ret
+    }
+
+    #[test]
+    fn test_alloc_merge_arranged() {
+        timely::execute_directly(|worker| {
+            let mut group = UpsertInputGroup::<SysTime, SysDiff>::new();
+            let mut traces = TraceGroup::new();
+            let (counters, named) = worker.dataflow(|scope| {
+                let (_, counters) = group.alloc_merge_arranged::<Counter, _>(scope, &mut traces);
+                let (_, named) =
+                    group.alloc_merge_arranged_named::<Counter, _>("named", scope, &mut traces);
+                (counters, named)
+            });
+            assert_eq!(named.name(), "named");
+            group.patch_to(&counters, [((), 2), ((), 3)]);
+            group.patch_to(&named, [((), 4)]);
+            group.advance_to(1.into());
+            group.patch::<Counter>((), -5);
+            group.advance_to(2.into());
+
+            type CounterTrace = UpsertTrace<Counter, SysTime, SysDiff>;
+            let mut trace = traces.get::<CounterTrace>().unwrap().clone();
+            step_past(worker, &mut trace, 1);
+            assert_eq!(read_count(&mut trace, 0), Some(5));
+            // the count merged to zero is deleted
+            assert_eq!(read_count(&mut trace, 1), None);
+
+            let mut trace = traces.get_named::<CounterTrace>("named").unwrap().clone();
+            step_past(worker, &mut trace, 1);
+            assert_eq!(read_count(&mut trace, 1), Some(4));
+        });
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+use std::sync::Arc;
+
+use timely::communication::Allocator;
+use timely::dataflow::scopes::Child;
+use timely::worker::Worker;
+
+use crate::{SysTime, WorkerState};
+
+/// The scope a view's dataflow is built in.
+pub type ViewScope<'a> = Child<'a, Worker<Allocator>, SysTime>;
+
+/// Builds a view's dataflow, runs once on every worker.
+#[derive(Clone)]
+pub(crate) struct ViewBuilder(
+    pub(crate) Arc<dyn Fn(&mut ViewScope<'_>, WorkerState<'_>) + Send + Sync>,
+);
+
+impl fmt::Debug for ViewBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("ViewBuilder { .. }")
+    }
+}
+
+/// A dataflow installed at runtime, dropping it deregisters its traces.
+pub(crate) struct InstalledView {
+    pub(crate) dataflow: usize,
+    pub(crate) traces: Vec<String>,
+}